@@ -1,5 +1,506 @@
 pub mod alacritty;
-pub mod x;
-pub mod xterm;
 pub mod kitty;
 pub mod termite;
+pub mod x;
+pub mod xterm;
+
+use crate::palette::Palette;
+use crate::settings::{self, Settings};
+use crate::{literal, ColorName, Theme, RGBA};
+use failure::{err_msg, Error};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_default()
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config` if it isn't set. This is also used on
+/// MacOS, where terminal emulators don't keep their configs in
+/// Library/Preferences either.
+pub fn config_home() -> PathBuf {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir().join(".config"),
+    }
+}
+
+/// The colors on the lines of `config` that `line` matches, taken from its
+/// `color_name` and `color_value` groups. Config keys become theme color
+/// names, e.g., `url_color` becomes `url`. If a color is set more than once,
+/// the last line wins, like in the apps.
+pub fn capture_colors(config: &str, line: &Regex) -> Result<Vec<(ColorName, RGBA)>, Error> {
+    let mut colors: Vec<(ColorName, RGBA)> = Vec::new();
+
+    for captures in config.lines().filter_map(|l| line.captures(l)) {
+        let name = Palette::canonical_name(&captures["color_name"].to_lowercase()).to_owned();
+        let value = literal::parse_color(&captures["color_value"]).map_err(err_msg)?;
+
+        match colors.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = value,
+            None => colors.push((name, value)),
+        }
+    }
+
+    Ok(colors)
+}
+
+/// Appends `line` to `config`, unless `config` already has that line
+pub fn append_line(config: &str, line: &str) -> String {
+    if config.lines().any(|l| l.trim() == line) {
+        return config.to_owned();
+    }
+
+    let mut result = config.to_owned();
+
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(line);
+    result.push('\n');
+
+    result
+}
+
+/// `path` with the home directory replaced by `~`
+pub fn tilde(path: &Path) -> String {
+    match path.strip_prefix(home_dir()) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// A config key that kept its old value because the theme has no color for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingColor {
+    /// The key as it appears in the app's config, e.g., `bright.red`
+    pub key: String,
+    /// The theme color that would have been used, e.g., `color9`
+    pub color: ColorName,
+}
+
+/// The output of a backend's `convert`: the new config and which theme
+/// colors it did or did not find.
+#[derive(Debug, Default, PartialEq)]
+pub struct Conversion {
+    pub config: String,
+    pub missing: Vec<MissingColor>,
+    pub used: BTreeSet<ColorName>,
+}
+
+impl Conversion {
+    /// Looks up `color` in the theme and records it as either used or, for
+    /// the config key `key`, missing. `color` can be an alias like `text`.
+    pub fn lookup<'t>(&mut self, theme: &'t Theme, key: &str, color: &str) -> Option<&'t RGBA> {
+        let value = theme.colors.get(color);
+        let color = Palette::canonical_name(color).to_owned();
+
+        match value {
+            Some(_) => {
+                self.used.insert(color);
+            }
+            None => self.missing.push(MissingColor {
+                key: key.to_owned(),
+                color,
+            }),
+        }
+
+        value
+    }
+}
+
+/// The `N` of `colorN`
+pub fn color_index(name: &str) -> Option<u32> {
+    name.strip_prefix("color").and_then(|n| n.parse().ok())
+}
+
+/// Sorts color names the way configs usually list them: `color0` to
+/// `color255` in numeric order, then everything else alphabetically.
+pub fn sort_color_names(names: &mut [String]) {
+    names.sort_by(|a, b| compare_color_names(a, b));
+}
+
+pub fn compare_color_names(a: &str, b: &str) -> Ordering {
+    match (color_index(a), color_index(b)) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// The config keys, and the theme colors for them, that are not in
+/// `present`. `config_key` maps a palette name to the app's key for it, if
+/// the app has one. Sorted by key with `sort_color_names`.
+pub fn missing_keys<F>(
+    theme: &Theme,
+    present: &HashSet<String>,
+    config_key: F,
+) -> Vec<(String, ColorName)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut missing: Vec<(String, ColorName)> = theme
+        .colors
+        .names()
+        .into_iter()
+        .filter_map(|name| config_key(&name).map(|key| (key, name)))
+        .filter(|(key, _)| !present.contains(key))
+        .collect();
+
+    missing.sort_by(|(a, _), (b, _)| compare_color_names(a, b));
+
+    missing
+}
+
+/// Appends `lines` to the end of `config`
+pub fn append_lines(config: &str, lines: &[String]) -> String {
+    if lines.is_empty() {
+        return config.to_owned();
+    }
+
+    let mut result = config.trim_end_matches('\n').to_owned();
+
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    result.push_str(&lines.join("\n"));
+
+    result
+}
+
+/// The X resource name for the theme color `name`
+fn xresources_key(name: &str) -> Option<String> {
+    let known = match color_index(name) {
+        Some(i) => i <= 255,
+        None => name == "foreground" || name == "background",
+    };
+
+    if known {
+        Some(name.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Appends `<prefix>colorN` entries to an Xresources file for the theme
+/// colors it doesn't set yet. `prefix` is the part before the resource name,
+/// e.g., `*.` or `XTerm*`. A trailing `.` in it is optional in the file.
+pub fn insert_xresources(
+    theme: &Theme,
+    conversion: &mut Conversion,
+    prefix: &str,
+) -> Result<(), Error> {
+    let re_key = Regex::new(&format!(
+        r"(?i)^{}\.?(?P<key>\w+)\s*:",
+        regex::escape(prefix.trim_end_matches('.'))
+    ))?;
+
+    let present: HashSet<String> = conversion
+        .config
+        .lines()
+        .filter_map(|line| re_key.captures(line))
+        .map(|captures| captures["key"].to_owned())
+        .collect();
+
+    let lines: Vec<String> = missing_keys(theme, &present, xresources_key)
+        .into_iter()
+        .filter_map(|(key, color)| {
+            let line = format!("{}{}: {}", prefix, key, theme.colors.get(&color)?.to_hex());
+            conversion.used.insert(color);
+            Some(line)
+        })
+        .collect();
+
+    conversion.config = append_lines(&conversion.config, &lines);
+
+    Ok(())
+}
+
+/// Starts a line comment in Xresources files. `#` starts a preprocessor
+/// directive for xrdb.
+pub const XRESOURCES_COMMENT: &str = "!";
+
+/// Adds an xrdb `#include` for `include` to an Xresources file. Only the
+/// file name is used, since xrdb looks for quoted includes next to the
+/// including file first.
+pub fn add_xresources_include(config: &str, include: &Path) -> String {
+    let file_name = include.file_name().unwrap_or_default().to_string_lossy();
+
+    append_line(config, &format!("#include \"{}\"", file_name))
+}
+
+/// A terminal emulator (or anything else with a color config) that teems can
+/// theme. Implement this and add it to a `Registry` to support a new app.
+pub trait TerminalBackend {
+    /// Short name used on the command line and in messages
+    fn name(&self) -> &str;
+
+    /// Where the app looks for its config files
+    fn default_config_paths(&self) -> Vec<PathBuf>;
+
+    /// Whether the app seems to be in use on this machine
+    fn detect(&self) -> bool {
+        self.default_config_paths().iter().any(|p| p.exists())
+    }
+
+    /// Whether the app has a key for the derived color `name`, see
+    /// `derivation::derive`. Most apps only have the 16 ANSI colors and
+    /// don't get the color cube or the dim colors.
+    fn uses_derived(&self, name: &str) -> bool {
+        color_index(name).is_some_and(|i| i < 16)
+    }
+
+    /// Replaces the colors in `config` with those from `theme`
+    fn convert(&self, theme: &Theme, config: &str) -> Result<Conversion, Error>;
+
+    /// The colors set in `config`, the inverse of `convert`
+    fn capture(&self, _config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+        Ok(vec![])
+    }
+
+    /// Adds entries for the theme colors that `conversion.config` (the
+    /// output of `convert`) has no key for, in the app's own syntax. Backends
+    /// that can't do this leave the config alone.
+    fn insert_missing(&self, _theme: &Theme, _conversion: &mut Conversion) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The app's color settings for `theme`, as they would appear in a fresh
+    /// config file
+    fn render(&self, theme: &Theme) -> Result<Conversion, Error> {
+        let mut conversion = Conversion::default();
+        self.insert_missing(theme, &mut conversion)?;
+
+        Ok(conversion)
+    }
+
+    /// The file the include strategy renders the theme into, for the main
+    /// config at `config_path`. `None` if the app can't include other files.
+    fn include_path(&self, _config_path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Adds the directive that pulls `include` into `config`, unless it is
+    /// already there
+    fn add_include(&self, config: &str, _include: &Path) -> Result<String, Error> {
+        Ok(config.to_owned())
+    }
+
+    /// What starts a line comment in the app's config
+    fn comment(&self) -> &str {
+        "#"
+    }
+
+    /// Fails if the block strategy can't append its block to `config`, which
+    /// has none yet
+    fn check_new_block(&self, _config: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Makes running instances pick up the new config in `paths`, which
+    /// are all of the app's config files. Most apps only read their config
+    /// on startup, so this does nothing by default.
+    fn reload(&self, _theme: &Theme, _paths: &[PathBuf]) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The backends an activation runs through, in registration order, and the
+/// user's settings for them.
+#[derive(Default)]
+pub struct Registry {
+    backends: Vec<Box<dyn TerminalBackend>>,
+    settings: Settings,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// A registry with all backends that ship with teems
+    pub fn with_defaults() -> Registry {
+        let mut registry = Registry::new();
+
+        registry.register(Box::new(alacritty::Alacritty));
+        registry.register(Box::new(x::X));
+        registry.register(Box::new(xterm::XTerm));
+        registry.register(Box::new(kitty::Kitty));
+        registry.register(Box::new(termite::Termite));
+
+        registry
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Adds `backend`, replacing any registered backend with the same name
+    pub fn register(&mut self, backend: Box<dyn TerminalBackend>) {
+        match self
+            .backends
+            .iter()
+            .position(|b| b.name() == backend.name())
+        {
+            Some(i) => self.backends[i] = backend,
+            None => self.backends.push(backend),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn TerminalBackend> {
+        self.iter().find(|b| b.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn TerminalBackend> {
+        self.backends.iter().map(|b| b.as_ref())
+    }
+
+    /// Backends that are not disabled in the settings
+    pub fn enabled(&self) -> impl Iterator<Item = &dyn TerminalBackend> {
+        self.iter()
+            .filter(move |b| self.settings.is_enabled(b.name()))
+    }
+
+    /// The existing config files of `backend`, using the paths from the
+    /// settings if there are any. They keep the order they were given in.
+    pub fn config_paths(&self, backend: &dyn TerminalBackend) -> Vec<PathBuf> {
+        let app_settings = self.settings.app(backend.name());
+
+        let mut paths: Vec<PathBuf> = match app_settings.and_then(|a| a.paths.as_ref()) {
+            Some(paths) => paths.iter().map(|p| settings::expand_home(p)).collect(),
+            None => backend.default_config_paths(),
+        };
+
+        if let Some(app_settings) = app_settings {
+            paths.extend(
+                app_settings
+                    .extra_paths
+                    .iter()
+                    .map(|p| settings::expand_home(p)),
+            );
+        }
+
+        let mut seen = HashSet::new();
+
+        paths
+            .into_iter()
+            .filter(|p| p.exists() && seen.insert(p.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+
+    impl TerminalBackend for Fake {
+        fn name(&self) -> &str {
+            "kitty"
+        }
+
+        fn default_config_paths(&self) -> Vec<PathBuf> {
+            vec![]
+        }
+
+        fn convert(&self, _theme: &Theme, config: &str) -> Result<Conversion, Error> {
+            Ok(Conversion {
+                config: config.to_owned(),
+                ..Conversion::default()
+            })
+        }
+    }
+
+    #[test]
+    fn it_sorts_color_names() {
+        let mut names: Vec<String> = vec!["foreground", "color10", "background", "color2"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        sort_color_names(&mut names);
+
+        assert_eq!(names, vec!["color2", "color10", "background", "foreground"]);
+    }
+
+    #[test]
+    fn it_inserts_xresources_after_either_binding() {
+        let colors: Palette = vec![
+            (String::from("color0"), RGBA(0, 0, 0, 1.0)),
+            (String::from("color1"), RGBA(1, 1, 1, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+        let theme = Theme {
+            name: String::from("theme"),
+            extends: None,
+            colors,
+        };
+        let mut conversion = Conversion {
+            config: String::from("XTerm*.color0: #ffffff"),
+            ..Conversion::default()
+        };
+
+        insert_xresources(&theme, &mut conversion, "XTerm*").unwrap();
+
+        assert_eq!(
+            conversion.config,
+            "XTerm*.color0: #ffffff\nXTerm*color1: #010101"
+        );
+    }
+
+    #[test]
+    fn it_replaces_backends_with_the_same_name() {
+        let mut registry = Registry::with_defaults();
+        let count = registry.iter().count();
+
+        registry.register(Box::new(Fake));
+
+        assert_eq!(registry.iter().count(), count);
+        assert!(registry
+            .get("kitty")
+            .unwrap()
+            .default_config_paths()
+            .is_empty());
+    }
+
+    #[test]
+    fn it_uses_paths_from_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main.conf");
+        let extra = dir.path().join("extra.conf");
+        std::fs::write(&main, "").unwrap();
+        std::fs::write(&extra, "").unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(Box::new(Fake));
+        registry.set_settings(
+            Settings::parse(&format!(
+                "[apps.kitty]\npaths = [{:?}, \"/does/not/exist\"]\nextra_paths = [{:?}, {:?}]",
+                main, extra, main
+            ))
+            .unwrap(),
+        );
+
+        let kitty = registry.get("kitty").unwrap();
+        assert_eq!(registry.config_paths(kitty), vec![main, extra]);
+    }
+
+    #[test]
+    fn it_skips_disabled_backends() {
+        let mut registry = Registry::with_defaults();
+        registry.set_settings(Settings::parse("[apps.xterm]\nenabled = false").unwrap());
+
+        assert!(registry.get("xterm").is_some());
+        assert!(registry.enabled().all(|b| b.name() != "xterm"));
+    }
+}
@@ -1,64 +1,97 @@
-use crate::Theme;
-use failure::Error;
+use crate::apps::{color_index, config_home, home_dir, tilde, Conversion, TerminalBackend};
+use crate::{literal, ColorName, Theme, RGBA};
+use failure::{err_msg, Error};
 use regex::Regex;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-enum Mode {
-    Bright,
-    Normal,
+/// The sections under `colors:`, their keys and the theme color for each
+/// key. Rewriting, capturing and inserting colors all go by this table.
+const SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "primary",
+        &[("background", "background"), ("foreground", "foreground")],
+    ),
+    ("cursor", &[("text", "cursor_text"), ("cursor", "cursor")]),
+    (
+        "normal",
+        &[
+            ("black", "color0"),
+            ("red", "color1"),
+            ("green", "color2"),
+            ("yellow", "color3"),
+            ("blue", "color4"),
+            ("magenta", "color5"),
+            ("cyan", "color6"),
+            ("white", "color7"),
+        ],
+    ),
+    (
+        "bright",
+        &[
+            ("black", "color8"),
+            ("red", "color9"),
+            ("green", "color10"),
+            ("yellow", "color11"),
+            ("blue", "color12"),
+            ("magenta", "color13"),
+            ("cyan", "color14"),
+            ("white", "color15"),
+        ],
+    ),
+    (
+        "dim",
+        &[
+            ("black", "dim_color0"),
+            ("red", "dim_color1"),
+            ("green", "dim_color2"),
+            ("yellow", "dim_color3"),
+            ("blue", "dim_color4"),
+            ("magenta", "dim_color5"),
+            ("cyan", "dim_color6"),
+            ("white", "dim_color7"),
+        ],
+    ),
+];
+
+/// The theme color for `key` in `section`
+fn theme_color(section: &str, key: &str) -> Option<&'static str> {
+    SECTIONS
+        .iter()
+        .find(|(name, _)| *name == section)
+        .and_then(|(_, keys)| keys.iter().find(|(k, _)| *k == key))
+        .map(|(_, color)| *color)
 }
 
-fn get_theme_color<'a>(color: &'a str, mode: &Mode) -> &'a str {
-    match mode {
-        Mode::Normal => match color {
-            "black" => "color0",
-            "red" => "color1",
-            "green" => "color2",
-            "yellow" => "color3",
-            "blue" => "color4",
-            "magenta" => "color5",
-            "cyan" => "color6",
-            "white" => "color7",
-            "foreground" => "foreground",
-            "background" => "background",
-            "text" => "text",
-            "cursor" => "cursor",
-            _ => "color0",
-        },
-        Mode::Bright => match color {
-            "black" => "color8",
-            "red" => "color9",
-            "green" => "color10",
-            "yellow" => "color11",
-            "blue" => "color12",
-            "magenta" => "color13",
-            "cyan" => "color14",
-            "white" => "color15",
-            "foreground" => "foreground",
-            "background" => "background",
-            "text" => "text",
-            "cursor" => "cursor",
-            _ => "color0",
-        },
-    }
+/// Pairs each line of `config` with the section from `SECTIONS` it is in.
+/// Lines in other sections, like `selection:`, get `None`. Lines before the
+/// first section count as `primary`.
+fn sections(config: &str) -> Result<Vec<(Option<&'static str>, &str)>, Error> {
+    let re_section = Regex::new(r"^\s*(?P<section>\w+):\s*(#.*)?$")?;
+    let mut section = Some("primary");
+
+    Ok(config
+        .lines()
+        .map(|line| {
+            if let Some(captures) = re_section.captures(line) {
+                section = SECTIONS
+                    .iter()
+                    .find(|(name, _)| *name == &captures["section"])
+                    .map(|(name, _)| *name);
+            }
+
+            (section, line)
+        })
+        .collect())
 }
 
-pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error> {
+pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<Conversion, Error> {
+    let mut conversion = Conversion::default();
     let re_line_with_color = Regex::new(
         r##"(?x)
             ^
             \s*
-            (?P<color_name>black
-                |red
-                |green
-                |yellow
-                |blue
-                |magenta
-                |cyan
-                |white
-                |foreground
-                |background
-                |cursor
-                |text)
+            (?P<color_name>\w+)
             :
             \s*['"]0x
             (?P<color_value>\w{6})
@@ -66,26 +99,23 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         "##,
     )?;
 
-    let mut mode = Mode::Normal;
     let mut results: Vec<String> = vec![];
 
-    for line in app_config.lines() {
-        let trimmed = line.trim_start();
+    for (section, line) in sections(app_config)? {
+        let found = re_line_with_color.captures(line).and_then(|captures| {
+            let section = section?;
+            let color = theme_color(section, &captures["color_name"])?;
 
-        if trimmed.starts_with("bright:") {
-            mode = Mode::Bright;
-        } else if trimmed.starts_with("normal:") {
-            mode = Mode::Normal;
-        }
+            Some((section, color, captures))
+        });
 
-        if let Some(captures) = re_line_with_color.captures(line) {
-            let theme_color_name = get_theme_color(&captures["color_name"], &mode);
+        if let Some((section, theme_color_name, captures)) = found {
+            let key = format!("{}.{}", section, &captures["color_name"]);
 
-            let new_value = &theme
-                .colors
+            let new_value = &conversion
                 // Use existing color value if theme doesn't have a replacement
-                .get(theme_color_name)
-                .and_then(|c| Some(c.to_hex()))
+                .lookup(theme, &key, theme_color_name)
+                .map(|c| c.to_hex())
                 .unwrap_or_else(|| captures["color_value"].to_string())
                 .replace("#", "");
 
@@ -97,17 +127,298 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         }
     }
 
-    Ok(results.join("\n"))
+    conversion.config = results.join("\n");
+
+    Ok(conversion)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// End of the YAML block that starts with the key at `start` and whose
+/// children are indented deeper than `level`
+fn block_end(lines: &[String], start: usize, level: usize) -> usize {
+    lines[start + 1..]
+        .iter()
+        .position(|l| is_content(l) && indent(l) <= level)
+        .map_or(lines.len(), |i| start + 1 + i)
+}
+
+/// Where to add children to the block from `start` to `end`: after its last
+/// child, so comments and blank lines before the next block stay there
+fn insert_position(lines: &[String], start: usize, end: usize) -> usize {
+    lines[start + 1..end]
+        .iter()
+        .rposition(|l| is_content(l))
+        .map_or(start + 1, |i| start + 2 + i)
+}
+
+/// Finds the `colors:` line and the line of `section` below it
+fn locate(lines: &[String], section: &str) -> Result<(Option<usize>, Option<usize>), Error> {
+    let re_colors = Regex::new(r"^\s*colors:\s*(#.*)?$")?;
+
+    let colors = match lines.iter().position(|l| re_colors.is_match(l)) {
+        Some(colors) => colors,
+        None => return Ok((None, None)),
+    };
+
+    let colors_end = block_end(lines, colors, indent(&lines[colors]));
+    let re_section = Regex::new(&format!(r"^\s*{}:\s*(#.*)?$", section))?;
+
+    let child_indent = lines[colors + 1..colors_end]
+        .iter()
+        .find(|l| is_content(l))
+        .map(|l| indent(l));
+
+    let section = lines[colors + 1..colors_end]
+        .iter()
+        .position(|l| Some(indent(l)) == child_indent && re_section.is_match(l))
+        .map(|i| colors + 1 + i);
+
+    Ok((Some(colors), section))
+}
+
+/// The colors set in the `colors:` sections of `config`. Other sections
+/// with the same keys, like `selection:`, are skipped.
+pub fn capture(config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+    let re_key = Regex::new(
+        r##"^\s*(?P<key>\w+):\s*['"]?(0x|\#)(?P<value>[0-9a-fA-F]{6})['"]?\s*(#.*)?$"##,
+    )?;
+
+    let mut colors: Vec<(ColorName, RGBA)> = Vec::new();
+
+    for (section, line) in sections(config)? {
+        let captures = match re_key.captures(line) {
+            Some(captures) => captures,
+            None => continue,
+        };
+
+        if let Some(color) = section.and_then(|section| theme_color(section, &captures["key"])) {
+            let value =
+                literal::parse_color(&format!("#{}", &captures["value"])).map_err(err_msg)?;
+
+            match colors.iter_mut().find(|(name, _)| name == color) {
+                Some(entry) => entry.1 = value,
+                None => colors.push((color.to_owned(), value)),
+            }
+        }
+    }
+
+    Ok(colors)
+}
+
+/// Adds the theme colors the config doesn't set yet to their section under
+/// `colors:`, e.g., `color9` becomes `red` in `colors.bright`. Missing
+/// sections are created.
+pub fn insert_missing(theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+    let re_key = Regex::new(r"^\s*(?P<key>\w+):")?;
+    let mut lines: Vec<String> = conversion.config.lines().map(String::from).collect();
+
+    for (section_name, keys) in SECTIONS {
+        let (colors, section) = locate(&lines, section_name)?;
+
+        let present: HashSet<String> = match section {
+            Some(section) => {
+                let end = block_end(&lines, section, indent(&lines[section]));
+
+                lines[section + 1..end]
+                    .iter()
+                    .filter_map(|line| re_key.captures(line))
+                    .map(|captures| captures["key"].to_owned())
+                    .collect()
+            }
+            None => HashSet::new(),
+        };
+
+        let missing: Vec<(&str, &str, &RGBA)> = keys
+            .iter()
+            .filter(|(key, _)| !present.contains(*key))
+            .filter_map(|(key, color)| Some((*key, *color, theme.colors.get(color)?)))
+            .collect();
+
+        if missing.is_empty() {
+            continue;
+        }
+
+        let colors = match colors {
+            Some(colors) => colors,
+            None => {
+                lines.push(String::from("colors:"));
+                lines.len() - 1
+            }
+        };
+
+        let colors_indent = indent(&lines[colors]);
+        let colors_end = block_end(&lines, colors, colors_indent);
+        let child_indent = lines[colors + 1..colors_end]
+            .iter()
+            .find(|l| is_content(l))
+            .map_or(colors_indent + 2, |l| indent(l));
+        let step = child_indent - colors_indent;
+
+        let (position, entry_indent, mut new_lines) = match section {
+            Some(section) => {
+                let end = block_end(&lines, section, child_indent);
+                let entry_indent = lines[section + 1..end]
+                    .iter()
+                    .find(|l| is_content(l))
+                    .map_or(child_indent + step, |l| indent(l));
+
+                (insert_position(&lines, section, end), entry_indent, vec![])
+            }
+            None => (
+                insert_position(&lines, colors, colors_end),
+                child_indent + step,
+                vec![format!("{}{}:", " ".repeat(child_indent), section_name)],
+            ),
+        };
+
+        for (key, color, value) in missing {
+            new_lines.push(format!(
+                "{}{}: '0x{}'",
+                " ".repeat(entry_indent),
+                key,
+                &value.to_hex()[1..]
+            ));
+            conversion.used.insert(color.to_owned());
+        }
+
+        lines.splice(position..position, new_lines);
+    }
+
+    conversion.config = lines.join("\n");
+
+    Ok(())
+}
+
+/// Whether `config` has a top level `colors:` key
+fn has_colors_key(config: &str) -> bool {
+    config.lines().any(|l| l.trim_end().starts_with("colors:"))
+}
+
+/// Adds `path` to the top level `import:` list, creating the list at the top
+/// of the file if there is none. Imported files are loaded before the main
+/// config, so colors set in the main config still win.
+pub fn add_import(config: &str, path: &str) -> Result<String, Error> {
+    let re_import = Regex::new(r"^import:\s*(#.*)?$")?;
+    let entry = format!("\"{}\"", path);
+
+    let lines: Vec<&str> = config.lines().collect();
+    let owned: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+
+    let import = match lines.iter().position(|l| re_import.is_match(l)) {
+        Some(import) => import,
+        None => return Ok(format!("import:\n  - {}\n\n{}", entry, config)),
+    };
+
+    let end = block_end(&owned, import, 0);
+
+    if lines[import + 1..end]
+        .iter()
+        .any(|l| l.trim().trim_start_matches('-').trim() == entry)
+    {
+        return Ok(config.to_owned());
+    }
+
+    let item_indent = lines[import + 1..end]
+        .iter()
+        .find(|l| is_content(l))
+        .map_or(2, |l| indent(l));
+
+    // Byte offset of the line after the last list item
+    let position = insert_position(&owned, import, end);
+    let offset: usize = config
+        .split_inclusive('\n')
+        .take(position)
+        .map(|l| l.len())
+        .sum();
+
+    let mut result = config[..offset].to_owned();
+    if !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(&format!("{}- {}\n", " ".repeat(item_indent), entry));
+    result.push_str(&config[offset..]);
+
+    Ok(result)
+}
+
+pub struct Alacritty;
+
+impl TerminalBackend for Alacritty {
+    fn name(&self) -> &str {
+        "alacritty"
+    }
+
+    fn default_config_paths(&self) -> Vec<PathBuf> {
+        vec![
+            config_home().join("alacritty/alacritty.yml"),
+            config_home().join("alacritty.yml"),
+            home_dir().join(".alacritty.yml"),
+        ]
+    }
+
+    fn uses_derived(&self, name: &str) -> bool {
+        name.starts_with("dim_color") || color_index(name).is_some_and(|i| i < 16)
+    }
+
+    fn convert(&self, theme: &Theme, config: &str) -> Result<Conversion, Error> {
+        convert_colors(theme, config)
+    }
+
+    fn capture(&self, config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+        capture(config)
+    }
+
+    fn insert_missing(&self, theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+        insert_missing(theme, conversion)
+    }
+
+    /// The block has its own `colors:` key, which YAML doesn't allow twice
+    fn check_new_block(&self, config: &str) -> Result<(), Error> {
+        if has_colors_key(config) {
+            return Err(err_msg(
+                "The config already has a 'colors:' key. Put '# BEGIN teems alacritty' and \
+                 '# END teems alacritty' around it to let teems replace it.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn include_path(&self, config_path: &Path) -> Option<PathBuf> {
+        Some(config_path.with_file_name("teems-colors.yml"))
+    }
+
+    /// Colors in the config itself override the imported ones, so the
+    /// import would change nothing
+    fn add_include(&self, config: &str, include: &Path) -> Result<String, Error> {
+        if has_colors_key(config) {
+            return Err(err_msg(
+                "The config has its own 'colors:' key, which overrides the imported theme. \
+                 Remove it to use the include strategy.",
+            ));
+        }
+
+        add_import(config, &tilde(include))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ColorName, RGBA};
-    use std::collections::HashMap;
+    use crate::block;
+    use crate::palette::Palette;
+    use crate::RGBA;
 
     fn get_theme() -> Theme {
-        let c: HashMap<ColorName, RGBA> = vec![
+        let c: Palette = vec![
             (String::from("color0"), RGBA(0, 0, 0, 1.0)),
             (String::from("color1"), RGBA(1, 1, 1, 1.0)),
             (String::from("color2"), RGBA(2, 2, 2, 1.0)),
@@ -134,10 +445,45 @@ mod tests {
 
         Theme {
             name: String::from("theme"),
+            extends: None,
             colors: c,
         }
     }
 
+    #[test]
+    fn it_does_not_add_a_second_colors_key() {
+        let theme = get_theme();
+
+        assert!(block::apply(&Alacritty, &theme, "colors:\n  primary: {}\n").is_err());
+
+        let cfg = "font:\n  size: 12\n# BEGIN teems alacritty\ncolors:\n# END teems alacritty\n";
+        let result = block::apply(&Alacritty, &theme, cfg).unwrap().config;
+        assert_eq!(result.matches("colors:").count(), 1);
+        assert!(result.starts_with("font:\n  size: 12\n# BEGIN teems alacritty\ncolors:\n"));
+    }
+
+    #[test]
+    fn it_captures_what_it_writes() {
+        let theme = get_theme();
+        let mut conversion = Conversion {
+            config: String::from("colors:\n  selection:\n    background: '0x123456'"),
+            ..Conversion::default()
+        };
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        let captured = capture(&conversion.config).unwrap();
+
+        assert_eq!(captured.len(), theme.colors.len());
+        for (name, color) in captured {
+            assert_eq!(theme.colors.get(&name), Some(&color), "{}", name);
+        }
+
+        assert_eq!(
+            capture("colors:\n  bright:\n    red: \"#ff0000\" # comment").unwrap(),
+            vec![(String::from("color9"), RGBA(255, 0, 0, 1.0))]
+        );
+    }
+
     #[test]
     fn it_replaces_colors() {
         let theme = get_theme();
@@ -204,7 +550,7 @@ mod tests {
             white:       '0x0f0f0f'
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
 
@@ -224,7 +570,121 @@ mod tests {
                 red:         '0x010101'
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn it_inserts_missing_colors() {
+        let theme = get_theme();
+
+        let cfg = "font:
+  size: 12
+colors:
+  primary:
+    background: '0x2E3440'
+
+  normal:
+    black: '0x3B4252'
+    red:   '0xBF616A'
+# Other settings
+window:
+  padding: 2";
+
+        let expected = "font:
+  size: 12
+colors:
+  primary:
+    background: '0x323232'
+    foreground: '0xffffff'
+
+  normal:
+    black: '0x000000'
+    red:   '0x010101'
+    green: '0x020202'
+    yellow: '0x030303'
+    blue: '0x040404'
+    magenta: '0x050505'
+    cyan: '0x060606'
+    white: '0x070707'
+  cursor:
+    text: '0x464646'
+    cursor: '0x3c3c3c'
+  bright:
+    black: '0x080808'
+    red: '0x090909'
+    green: '0x0a0a0a'
+    yellow: '0x0b0b0b'
+    blue: '0x0c0c0c'
+    magenta: '0x0d0d0d'
+    cyan: '0x0e0e0e'
+    white: '0x0f0f0f'
+# Other settings
+window:
+  padding: 2";
+
+        let mut conversion = convert_colors(&theme, cfg).unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        assert_eq!(conversion.config, expected);
+    }
+
+    #[test]
+    fn it_adds_a_colors_section() {
+        let mut theme = get_theme();
+        theme.colors.retain(|k, _| k == "color9");
+
+        let mut conversion = convert_colors(&theme, "font:\n  size: 12").unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        let expected = "font:\n  size: 12\ncolors:\n  bright:\n    red: '0x090909'";
+        assert_eq!(conversion.config, expected);
+    }
+
+    #[test]
+    fn it_adds_imports() {
+        let cfg = "font:\n  size: 12\n";
+
+        let result = add_import(cfg, "~/teems-colors.yml").unwrap();
+        assert_eq!(
+            result,
+            "import:\n  - \"~/teems-colors.yml\"\n\nfont:\n  size: 12\n"
+        );
+        assert_eq!(add_import(&result, "~/teems-colors.yml").unwrap(), result);
+
+        let cfg = "import:\n    - ~/base.yml\n# Font\nfont:\n  size: 12";
+        assert_eq!(
+            add_import(cfg, "~/teems-colors.yml").unwrap(),
+            "import:\n    - ~/base.yml\n    - \"~/teems-colors.yml\"\n# Font\nfont:\n  size: 12"
+        );
+    }
+
+    #[test]
+    fn it_refuses_to_import_over_a_colors_key() {
+        let include = Path::new("/home/user/.config/alacritty/teems-colors.yml");
+
+        assert!(Alacritty
+            .add_include("colors:\n  primary: {}\n", include)
+            .is_err());
+        assert!(Alacritty
+            .add_include("font:\n  size: 12\n", include)
+            .is_ok());
+    }
+
+    #[test]
+    fn it_skips_the_same_sections_as_capture() {
+        let theme = get_theme();
+        let cfg = "colors:\n  selection:\n    text: '0x123456'\n  cursor:\n    text: '0x123456'";
+
+        let conversion = convert_colors(&theme, cfg).unwrap();
+
+        assert_eq!(
+            conversion.config,
+            "colors:\n  selection:\n    text: '0x123456'\n  cursor:\n    text: '0x464646'"
+        );
+        assert_eq!(
+            capture(&conversion.config).unwrap(),
+            vec![(String::from("cursor_text"), RGBA(70, 70, 70, 1.0))]
+        );
+    }
 }
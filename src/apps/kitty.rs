@@ -1,16 +1,39 @@
-use crate::Theme;
+use crate::apps::{
+    append_line, append_lines, capture_colors, color_index, config_home, missing_keys, Conversion,
+    TerminalBackend,
+};
+#[cfg(unix)]
+use crate::hooks;
+use crate::{ColorName, Theme, RGBA};
+#[cfg(unix)]
+use failure::err_msg;
 use failure::Error;
 use regex::Regex;
+#[cfg(unix)]
+use serde_json::json;
+use std::collections::HashSet;
+use std::env;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 
-pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error> {
-    let mut results: Vec<String> = vec![];
-
-    let re_line_with_color = Regex::new(
+/// Matches the config lines that set a color
+fn color_line() -> Result<Regex, Error> {
+    Ok(Regex::new(
         r"(?x)
         ^\s*
         (?P<color_name>color\d+
             |foreground
             |background
+            |cursor_text_color
             |cursor
             |url_color
             |active_border_color
@@ -24,15 +47,26 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         \s*
         (?P<color_value>\#\w{6})
     ",
-    )?;
+    )?)
+}
+
+/// The colors set in `config`
+pub fn capture(config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+    capture_colors(config, &color_line()?)
+}
+
+pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<Conversion, Error> {
+    let mut conversion = Conversion::default();
+    let mut results: Vec<String> = vec![];
+
+    let re_line_with_color = color_line()?;
 
     for line in app_config.lines() {
         if let Some(captures) = re_line_with_color.captures(line) {
-            let new_value = &theme
-                .colors
+            let new_value = &conversion
                 // Use existing color value if theme doesn't have a replacement
-                .get(&captures["color_name"])
-                .and_then(|c| Some(c.to_hex()))
+                .lookup(theme, &captures["color_name"], &captures["color_name"])
+                .map(|c| c.to_hex())
                 .unwrap_or_else(|| captures["color_value"].to_string());
 
             let after = line.replace(&captures["color_value"], new_value);
@@ -43,17 +77,245 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         }
     }
 
-    Ok(results.join("\n"))
+    conversion.config = results.join("\n");
+
+    Ok(conversion)
+}
+
+/// The kitty.conf key for the theme color `name`
+fn config_key(name: &str) -> Option<String> {
+    match color_index(name) {
+        Some(i) if i <= 255 => Some(name.to_owned()),
+        Some(_) => None,
+        None if name == "url" => Some(String::from("url_color")),
+        None if name == "cursor_text" => Some(String::from("cursor_text_color")),
+        None => [
+            "foreground",
+            "background",
+            "cursor",
+            "active_border_color",
+            "inactive_border_color",
+            "active_tab_foreground",
+            "active_tab_background",
+            "inactive_tab_foreground",
+            "inactive_tab_background",
+            "selection_foreground",
+            "selection_background",
+        ]
+        .iter()
+        .find(|key| **key == name)
+        .map(|key| key.to_string()),
+    }
+}
+
+/// Appends the theme colors kitty.conf doesn't set yet to the end of it
+pub fn insert_missing(theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+    let re_key = Regex::new(r"^\s*(?P<key>\w+)\s")?;
+
+    let present: HashSet<String> = conversion
+        .config
+        .lines()
+        .filter_map(|line| re_key.captures(line))
+        .map(|captures| captures["key"].to_owned())
+        .collect();
+
+    let lines: Vec<String> = missing_keys(theme, &present, config_key)
+        .into_iter()
+        .filter_map(|(key, color)| {
+            let line = format!("{} {}", key, theme.colors.get(&color)?.to_hex());
+            conversion.used.insert(color);
+            Some(line)
+        })
+        .collect();
+
+    conversion.config = append_lines(&conversion.config, &lines);
+
+    Ok(())
+}
+
+/// The unix socket address from the last `listen_on` line in `config`
+pub fn listen_on(config: &str) -> Option<String> {
+    config
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+
+            match (parts.next(), parts.next()) {
+                (Some("listen_on"), Some(value)) => value.strip_prefix("unix:"),
+                _ => None,
+            }
+        })
+        .map(|address| address.to_owned())
+        .next()
+}
+
+/// The addresses a `listen_on` setting results in for the kitty processes
+/// in `pids`. Kitty replaces `{kitty_pid}` with its pid, or appends
+/// `-<pid>` if there is no placeholder. Relative paths are in the temp dir.
+pub fn socket_addresses(address: &str, pids: &[i32]) -> Vec<String> {
+    let address = if address.starts_with('@') || address.starts_with('/') {
+        address.to_owned()
+    } else {
+        env::temp_dir().join(address).display().to_string()
+    };
+
+    pids.iter()
+        .map(|pid| {
+            if address.contains("{kitty_pid}") {
+                address.replace("{kitty_pid}", &pid.to_string())
+            } else {
+                format!("{}-{}", address, pid)
+            }
+        })
+        .collect()
+}
+
+/// Tells the kitty listening on `address` to use the colors of `theme` in
+/// all windows, with the remote control protocol. Addresses starting with
+/// `@` are abstract sockets.
+#[cfg(unix)]
+pub fn set_colors(address: &str, theme: &Theme) -> Result<(), Error> {
+    let colors: serde_json::Map<String, serde_json::Value> = theme
+        .colors
+        .iter()
+        .filter_map(|(name, c)| {
+            let value = (u32::from(c.0) << 16) | (u32::from(c.1) << 8) | u32::from(c.2);
+            Some((config_key(&name)?, json!(value)))
+        })
+        .collect();
+
+    let command = json!({
+        "cmd": "set-colors",
+        "version": [0, 14, 2],
+        "no_response": true,
+        "payload": {
+            "colors": colors,
+            "match_window": null,
+            "match_tab": null,
+            "all": true,
+            "configured": true,
+            "reset": false,
+        },
+    });
+
+    let mut stream = connect(address)?;
+    stream.write_all(format!("\u{1b}P@kitty-cmd{}\u{1b}\\", command).as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn connect(address: &str) -> Result<UnixStream, Error> {
+    match address.strip_prefix('@') {
+        Some(name) => Ok(UnixStream::connect_addr(&SocketAddr::from_abstract_name(
+            name,
+        )?)?),
+        None => Ok(UnixStream::connect(address)?),
+    }
+}
+
+// Abstract sockets only exist on Linux
+#[cfg(all(unix, not(target_os = "linux")))]
+fn connect(address: &str) -> Result<UnixStream, Error> {
+    if address.starts_with('@') {
+        return Err(err_msg(format!(
+            "Abstract sockets like {} are only supported on Linux",
+            address
+        )));
+    }
+
+    Ok(UnixStream::connect(address)?)
+}
+
+pub struct Kitty;
+
+impl TerminalBackend for Kitty {
+    fn name(&self) -> &str {
+        "kitty"
+    }
+
+    fn default_config_paths(&self) -> Vec<PathBuf> {
+        vec![config_home().join("kitty/kitty.conf")]
+    }
+
+    fn uses_derived(&self, name: &str) -> bool {
+        color_index(name).is_some_and(|i| i <= 255)
+    }
+
+    fn convert(&self, theme: &Theme, config: &str) -> Result<Conversion, Error> {
+        convert_colors(theme, config)
+    }
+
+    fn capture(&self, config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+        capture(config)
+    }
+
+    fn insert_missing(&self, theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+        insert_missing(theme, conversion)
+    }
+
+    fn include_path(&self, config_path: &Path) -> Option<PathBuf> {
+        Some(config_path.with_file_name("teems-colors.conf"))
+    }
+
+    // Relative includes are resolved from the kitty config directory
+    fn add_include(&self, config: &str, include: &Path) -> Result<String, Error> {
+        let file_name = include.file_name().unwrap_or_default().to_string_lossy();
+
+        Ok(append_line(config, &format!("include {}", file_name)))
+    }
+
+    // Needs `allow_remote_control` and `listen_on` in kitty.conf, without a
+    // socket there is nothing to reload
+    #[cfg(unix)]
+    fn reload(&self, theme: &Theme, paths: &[PathBuf]) -> Result<(), Error> {
+        let mut addresses: Vec<String> = env::var("KITTY_LISTEN_ON")
+            .ok()
+            .and_then(|a| a.strip_prefix("unix:").map(|a| a.to_owned()))
+            .into_iter()
+            .collect();
+
+        // Without procfs only the kitty in $KITTY_LISTEN_ON can be found
+        let pids = if cfg!(target_os = "linux") {
+            hooks::user_processes("kitty")?
+        } else {
+            vec![]
+        };
+        for path in paths {
+            if let Some(address) = listen_on(&fs::read_to_string(path)?) {
+                addresses.extend(socket_addresses(&address, &pids));
+            }
+        }
+
+        addresses.sort();
+        addresses.dedup();
+
+        let failed: Vec<String> = addresses
+            .iter()
+            .filter(|a| a.starts_with('@') || Path::new(a).exists())
+            .filter_map(|a| set_colors(a, theme).err().map(|e| format!("{}: {}", a, e)))
+            .collect();
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(err_msg(format!(
+                "Could not set colors over {}",
+                failed.join(", ")
+            )))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ColorName, RGBA};
-    use std::collections::HashMap;
+    use crate::palette::Palette;
+    use crate::RGBA;
 
     fn get_theme() -> Theme {
-        let c: HashMap<ColorName, RGBA> = vec![
+        let c: Palette = vec![
             (String::from("color0"), RGBA(0, 0, 0, 1.0)),
             (String::from("color1"), RGBA(1, 1, 1, 1.0)),
             (String::from("color2"), RGBA(2, 2, 2, 1.0)),
@@ -82,6 +344,7 @@ mod tests {
 
         Theme {
             name: String::from("theme"),
+            extends: None,
             colors: c,
         }
     }
@@ -156,10 +419,29 @@ color14 #0e0e0e
 color15 #0f0f0f
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
 
+    #[test]
+    fn it_captures_colors() {
+        let cfg = "
+color1 #ff0000
+url_color #00ff00
+# color2 #0000ff
+color1 #aa0000
+font_size 12
+        ";
+
+        assert_eq!(
+            capture(cfg).unwrap(),
+            vec![
+                (String::from("color1"), RGBA(170, 0, 0, 1.0)),
+                (String::from("url"), RGBA(0, 255, 0, 1.0)),
+            ]
+        );
+    }
+
     #[test]
     fn it_does_not_affect_other_apps() {
         let theme = get_theme();
@@ -174,7 +456,100 @@ URxvt.foreground: #afb7c0
 URxvt.background: #2c2d30
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
+
+    #[test]
+    fn it_inserts_missing_colors() {
+        let mut theme = get_theme();
+        theme
+            .colors
+            .retain(|k, _| ["color0", "color12", "cursor"].contains(&k));
+
+        let cfg = "font_size 12\ncolor0 #ffffff\n";
+
+        let mut conversion = convert_colors(&theme, cfg).unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        let expected = "font_size 12\ncolor0 #000000\ncolor12 #0c0c0c\ncursor #3c3c3c";
+        assert_eq!(conversion.config, expected);
+    }
+
+    #[test]
+    fn it_maps_cursor_text() {
+        let mut theme = get_theme();
+        theme
+            .colors
+            .retain(|k, _| ["cursor", "cursor_text"].contains(&k));
+
+        let mut conversion = convert_colors(&theme, "cursor_text_color #ffffff").unwrap();
+        assert_eq!(conversion.config, "cursor_text_color #464646");
+        assert!(conversion.used.contains("cursor_text"));
+
+        conversion.config = String::from("font_size 12");
+        insert_missing(&theme, &mut conversion).unwrap();
+        assert_eq!(
+            conversion.config,
+            "font_size 12\ncursor #3c3c3c\ncursor_text_color #464646"
+        );
+        assert_eq!(
+            capture(&conversion.config).unwrap()[1],
+            (String::from("cursor_text"), RGBA(70, 70, 70, 1.0))
+        );
+    }
+
+    #[test]
+    fn it_finds_the_remote_control_socket() {
+        let cfg = "allow_remote_control yes\nlisten_on unix:/tmp/kitty\n";
+        let address = listen_on(cfg).unwrap();
+
+        assert_eq!(
+            socket_addresses(&address, &[12, 34]),
+            vec!["/tmp/kitty-12", "/tmp/kitty-34"]
+        );
+        assert_eq!(
+            socket_addresses("@kitty-{kitty_pid}.sock", &[12]),
+            vec!["@kitty-12.sock"]
+        );
+        assert_eq!(listen_on("font_size 12"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_sends_set_colors() {
+        use std::io::Read;
+        use std::os::unix::net::UnixListener;
+
+        let mut theme = get_theme();
+        theme
+            .colors
+            .retain(|k, _| ["color1", "foreground", "text"].contains(&k));
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("kitty-1");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        set_colors(&socket.to_string_lossy(), &theme).unwrap();
+
+        let mut message = String::new();
+        listener
+            .accept()
+            .unwrap()
+            .0
+            .read_to_string(&mut message)
+            .unwrap();
+
+        let json = message
+            .strip_prefix("\u{1b}P@kitty-cmd")
+            .and_then(|m| m.strip_suffix("\u{1b}\\"))
+            .unwrap();
+        let command: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        assert_eq!(command["cmd"], "set-colors");
+        assert_eq!(
+            command["payload"]["colors"],
+            json!({"color1": 0x010101, "foreground": 0xffffff})
+        );
+    }
 }
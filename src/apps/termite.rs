@@ -1,12 +1,23 @@
-use crate::Theme;
+use crate::apps::{
+    append_lines, capture_colors, color_index, config_home, missing_keys, Conversion,
+    TerminalBackend,
+};
+#[cfg(unix)]
+use crate::hooks;
 use crate::RGBA;
+use crate::{ColorName, Theme};
 use failure::Error;
 use regex::Regex;
+use std::collections::HashSet;
+use std::path::PathBuf;
 
-pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error> {
-    let mut results: Vec<String> = vec![];
+fn format_color(RGBA(r, g, b, a): &RGBA) -> String {
+    format!("rgba({},{},{},{})", r, g, b, a)
+}
 
-    let re_line_with_color = Regex::new(
+/// Matches the config lines that set a color
+fn color_line() -> Result<Regex, Error> {
+    Ok(Regex::new(
         r"(?xi)
         ^
         (?P<color_name>color\d+
@@ -19,14 +30,26 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         \s*=\s*
         (?P<color_value>(\#\w{6}|rgba\(.*\)))
     ",
-    )?;
+    )?)
+}
+
+/// The colors set in `config`
+pub fn capture(config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+    capture_colors(config, &color_line()?)
+}
+
+pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<Conversion, Error> {
+    let mut conversion = Conversion::default();
+    let mut results: Vec<String> = vec![];
+
+    let re_line_with_color = color_line()?;
 
     for line in app_config.lines() {
         if let Some(captures) = re_line_with_color.captures(line) {
-            let new_value = &theme
-                .colors
-                .get(&captures["color_name"])
-                .and_then(|RGBA(r, b, g, a)| Some(format!("rgba({},{},{},{})", r, g, b, a)))
+            let new_value = &conversion
+                // Use existing color value if theme doesn't have a replacement
+                .lookup(theme, &captures["color_name"], &captures["color_name"])
+                .map(format_color)
                 .unwrap_or_else(|| captures["color_value"].to_string());
 
             let after = line.replace(&captures["color_value"], new_value);
@@ -37,17 +60,129 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         }
     }
 
-    Ok(results.join("\n"))
+    conversion.config = results.join("\n");
+
+    Ok(conversion)
+}
+
+/// The termite config key for the theme color `name`
+fn config_key(name: &str) -> Option<String> {
+    let key = match color_index(name) {
+        Some(i) if i <= 255 => name,
+        Some(_) => return None,
+        None => match name {
+            "foreground" | "background" | "cursor" | "foreground_bold" => name,
+            "cursor_text" => "cursor_foreground",
+            "selection_background" => "highlight",
+            _ => return None,
+        },
+    };
+
+    Some(key.to_owned())
+}
+
+/// Adds the theme colors the config doesn't set yet to the end of its
+/// `[colors]` section, creating the section if there is none
+pub fn insert_missing(theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+    let re_key = Regex::new(r"^\s*(?P<key>\w+)\s*=")?;
+    let re_section = Regex::new(r"^\s*\[(?P<name>[^\]]*)\]")?;
+
+    let mut lines: Vec<String> = conversion.config.lines().map(String::from).collect();
+
+    let start = lines.iter().position(|l| {
+        re_section
+            .captures(l)
+            .is_some_and(|c| &c["name"] == "colors")
+    });
+
+    let end = match start {
+        Some(start) => lines[start + 1..]
+            .iter()
+            .position(|l| re_section.is_match(l))
+            .map_or(lines.len(), |i| start + 1 + i),
+        None => lines.len(),
+    };
+
+    let present: HashSet<String> = match start {
+        Some(start) => lines[start + 1..end]
+            .iter()
+            .filter_map(|line| re_key.captures(line))
+            .map(|captures| captures["key"].to_owned())
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let mut new_lines: Vec<String> = missing_keys(theme, &present, config_key)
+        .into_iter()
+        .filter_map(|(key, color)| {
+            let line = format!("{} = {}", key, format_color(theme.colors.get(&color)?));
+            conversion.used.insert(color);
+            Some(line)
+        })
+        .collect();
+
+    if new_lines.is_empty() {
+        return Ok(());
+    }
+
+    match start {
+        Some(start) => {
+            // After the last entry, so blank lines before the next section stay
+            let last_entry = lines[start + 1..end]
+                .iter()
+                .rposition(|l| !l.trim().is_empty())
+                .map_or(start, |i| start + 1 + i);
+
+            lines.splice(last_entry + 1..last_entry + 1, new_lines);
+            conversion.config = lines.join("\n");
+        }
+        None => {
+            new_lines.insert(0, String::from("[colors]"));
+            conversion.config = append_lines(&conversion.config, &new_lines);
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Termite;
+
+impl TerminalBackend for Termite {
+    fn name(&self) -> &str {
+        "termite"
+    }
+
+    fn default_config_paths(&self) -> Vec<PathBuf> {
+        vec![config_home().join("termite/config")]
+    }
+
+    fn convert(&self, theme: &Theme, config: &str) -> Result<Conversion, Error> {
+        convert_colors(theme, config)
+    }
+
+    fn capture(&self, config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+        capture(config)
+    }
+
+    fn insert_missing(&self, theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+        insert_missing(theme, conversion)
+    }
+
+    // Termite reloads its config on SIGUSR1
+    #[cfg(unix)]
+    fn reload(&self, _theme: &Theme, _paths: &[PathBuf]) -> Result<(), Error> {
+        hooks::signal_processes("termite", libc::SIGUSR1)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ColorName, RGBA};
-    use std::collections::HashMap;
+    use crate::palette::Palette;
+    use crate::RGBA;
 
     fn get_theme() -> Theme {
-        let c: HashMap<ColorName, RGBA> = vec![
+        let c: Palette = vec![
             (String::from("color0"), RGBA(0, 0, 0, 1.0)),
             (String::from("color1"), RGBA(1, 1, 1, 1.0)),
             (String::from("color2"), RGBA(2, 2, 2, 1.0)),
@@ -74,6 +209,7 @@ mod tests {
 
         Theme {
             name: String::from("theme"),
+            extends: None,
             colors: c,
         }
     }
@@ -134,7 +270,7 @@ color14 = rgba(14,14,14,1)
 color15 = rgba(15,15,15,1)
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
 
@@ -152,7 +288,48 @@ URxvt.foreground: #afb7c0
 URxvt.background: #2c2d30
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
+
+    #[test]
+    fn it_inserts_missing_colors_into_the_colors_section() {
+        let mut theme = get_theme();
+        theme
+            .colors
+            .retain(|k, _| ["color0", "color12", "cursor"].contains(&k));
+
+        let cfg = "
+[colors]
+color0 = #ffffff
+
+[options]
+font = Monospace 9";
+
+        let expected = "
+[colors]
+color0 = rgba(0,0,0,1)
+color12 = rgba(12,12,12,1)
+cursor = rgba(60,60,60,1)
+
+[options]
+font = Monospace 9";
+
+        let mut conversion = convert_colors(&theme, cfg).unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        assert_eq!(conversion.config, expected);
+    }
+
+    #[test]
+    fn it_adds_a_colors_section() {
+        let mut theme = get_theme();
+        theme.colors.retain(|k, _| k == "color1");
+
+        let mut conversion = convert_colors(&theme, "[options]\nfont = Monospace 9").unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        let expected = "[options]\nfont = Monospace 9\n[colors]\ncolor1 = rgba(1,1,1,1)";
+        assert_eq!(conversion.config, expected);
+    }
 }
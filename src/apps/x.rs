@@ -1,11 +1,16 @@
-use crate::Theme;
+use crate::apps::{
+    add_xresources_include, capture_colors, home_dir, insert_xresources, Conversion,
+    TerminalBackend, XRESOURCES_COMMENT,
+};
+use crate::hooks;
+use crate::{ColorName, Theme, RGBA};
 use failure::Error;
 use regex::Regex;
+use std::path::{Path, PathBuf};
 
-pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error> {
-    let mut results: Vec<String> = vec![];
-
-    let re_line_with_color = Regex::new(
+/// Matches the config lines that set a color
+fn color_line() -> Result<Regex, Error> {
+    Ok(Regex::new(
         r"(?x)
         ^\*.
         (?P<color_name>color\d+
@@ -14,15 +19,26 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         :\s*
         (?P<color_value>\#\w{6})
     ",
-    )?;
+    )?)
+}
+
+/// The colors set in `config`
+pub fn capture(config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+    capture_colors(config, &color_line()?)
+}
+
+pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<Conversion, Error> {
+    let mut conversion = Conversion::default();
+    let mut results: Vec<String> = vec![];
+
+    let re_line_with_color = color_line()?;
 
     for line in app_config.lines() {
         if let Some(captures) = re_line_with_color.captures(line) {
-            let new_value = &theme
-                .colors
+            let new_value = &conversion
                 // Use existing color value if theme doesn't have a replacement
-                .get(&captures["color_name"])
-                .and_then(|c| Some(c.to_hex()))
+                .lookup(theme, &captures["color_name"], &captures["color_name"])
+                .map(|c| c.to_hex())
                 .unwrap_or_else(|| captures["color_value"].to_string());
 
             let after = line.replace(&captures["color_value"], new_value);
@@ -33,17 +49,64 @@ pub fn convert_colors(theme: &Theme, app_config: &str) -> Result<String, Error>
         }
     }
 
-    Ok(results.join("\n"))
+    conversion.config = results.join("\n");
+
+    Ok(conversion)
+}
+
+/// Appends `*.colorN` entries for the theme colors the file doesn't set yet
+pub fn insert_missing(theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+    insert_xresources(theme, conversion, "*.")
+}
+
+pub struct X;
+
+impl TerminalBackend for X {
+    fn name(&self) -> &str {
+        "x"
+    }
+
+    fn default_config_paths(&self) -> Vec<PathBuf> {
+        vec![home_dir().join(".Xresources")]
+    }
+
+    fn convert(&self, theme: &Theme, config: &str) -> Result<Conversion, Error> {
+        convert_colors(theme, config)
+    }
+
+    fn capture(&self, config: &str) -> Result<Vec<(ColorName, RGBA)>, Error> {
+        capture(config)
+    }
+
+    fn insert_missing(&self, theme: &Theme, conversion: &mut Conversion) -> Result<(), Error> {
+        insert_missing(theme, conversion)
+    }
+
+    fn include_path(&self, config_path: &Path) -> Option<PathBuf> {
+        Some(config_path.with_file_name(".Xresources.teems"))
+    }
+
+    fn add_include(&self, config: &str, include: &Path) -> Result<String, Error> {
+        Ok(add_xresources_include(config, include))
+    }
+
+    fn comment(&self) -> &str {
+        XRESOURCES_COMMENT
+    }
+
+    fn reload(&self, _theme: &Theme, paths: &[PathBuf]) -> Result<(), Error> {
+        hooks::xrdb_merge(paths)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ColorName, RGBA};
-    use std::collections::HashMap;
+    use crate::palette::Palette;
+    use crate::RGBA;
 
     fn get_theme() -> Theme {
-        let c: HashMap<ColorName, RGBA> = vec![
+        let c: Palette = vec![
             (String::from("color0"), RGBA(0, 0, 0, 1.0)),
             (String::from("color1"), RGBA(1, 1, 1, 1.0)),
             (String::from("color2"), RGBA(2, 2, 2, 1.0)),
@@ -70,6 +133,7 @@ mod tests {
 
         Theme {
             name: String::from("theme"),
+            extends: None,
             colors: c,
         }
     }
@@ -120,7 +184,7 @@ mod tests {
 *.color15: #0f0f0f
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
 
@@ -138,7 +202,26 @@ URxvt.foreground: #afb7c0
 URxvt.background: #2c2d30
         ";
 
-        let result = convert_colors(&theme, &cfg).unwrap();
+        let result = convert_colors(&theme, cfg).unwrap().config;
         assert_eq!(result, cfg_expected);
     }
+
+    #[test]
+    fn it_inserts_missing_colors() {
+        let mut theme = get_theme();
+        theme
+            .colors
+            .retain(|k, _| ["color0", "color1", "background"].contains(&k));
+
+        let cfg = "URxvt.font: xft:Monospace\n*.color0: #ffffff";
+
+        let mut conversion = convert_colors(&theme, cfg).unwrap();
+        insert_missing(&theme, &mut conversion).unwrap();
+
+        let expected = "URxvt.font: xft:Monospace
+*.color0: #000000
+*.color1: #010101
+*.background: #323232";
+        assert_eq!(conversion.config, expected);
+    }
 }
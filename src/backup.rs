@@ -0,0 +1,225 @@
+use failure::Error;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BackupFile {
+    /// Where the file lives and where `undo` restores it to
+    pub path: PathBuf,
+    /// Name of the copy inside the activation's backup directory. `None` if
+    /// the file didn't exist before, `undo` removes it then.
+    #[serde(default)]
+    pub backup: Option<String>,
+}
+
+/// One theme activation and the files it modified, as they were before.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Activation {
+    pub id: String,
+    pub theme: String,
+    pub timestamp: u64,
+    pub files: Vec<BackupFile>,
+}
+
+impl Activation {
+    /// The activation time as `YYYY-MM-DD HH:MM:SS UTC`
+    pub fn date(&self) -> String {
+        format_timestamp(self.timestamp)
+    }
+}
+
+/// Versioned copies of config files, one directory per activation. Directory
+/// names sort in activation order.
+pub struct BackupStore {
+    root: PathBuf,
+}
+
+impl BackupStore {
+    pub fn new(root: PathBuf) -> BackupStore {
+        BackupStore { root }
+    }
+
+    /// `$XDG_STATE_HOME/teems/backups`, falling back to `~/.local/state`
+    pub fn default_location() -> Result<BackupStore, Error> {
+        let state_dir = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => dirs::home_dir()
+                .ok_or_else(|| failure::err_msg("Could not determine home directory"))?
+                .join(".local/state"),
+        };
+
+        Ok(BackupStore::new(state_dir.join("teems").join("backups")))
+    }
+
+    /// Copies every file in `paths` into a new backup directory and records
+    /// the activation of `theme`.
+    pub fn snapshot(&self, theme: &str, paths: &[PathBuf]) -> Result<Activation, Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let id = format!("{:020}", now.as_nanos());
+        let dir = self.root.join(&id);
+
+        fs::create_dir_all(&dir)?;
+
+        let mut files = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            let backup = if path.exists() {
+                let backup = i.to_string();
+                fs::copy(path, dir.join(&backup))?;
+                Some(backup)
+            } else {
+                None
+            };
+
+            files.push(BackupFile {
+                path: path.clone(),
+                backup,
+            });
+        }
+
+        let activation = Activation {
+            id,
+            theme: theme.to_owned(),
+            timestamp: now.as_secs(),
+            files,
+        };
+
+        fs::write(
+            dir.join(MANIFEST),
+            serde_json::to_string_pretty(&activation)?,
+        )?;
+
+        Ok(activation)
+    }
+
+    /// Removes `activation` from the history without restoring its files,
+    /// for an activation that didn't go through
+    pub fn discard(&self, activation: &Activation) -> Result<(), Error> {
+        fs::remove_dir_all(self.root.join(&activation.id))?;
+
+        Ok(())
+    }
+
+    /// All recorded activations, oldest first
+    pub fn history(&self) -> Result<Vec<Activation>, Error> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut dirs: Vec<PathBuf> = fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.join(MANIFEST).is_file())
+            .collect();
+
+        dirs.sort();
+
+        dirs.iter().map(|dir| read_manifest(dir)).collect()
+    }
+
+    /// Restores the files of the most recent activation and removes it from
+    /// the history. Returns `None` if there is nothing to undo.
+    pub fn undo(&self) -> Result<Option<Activation>, Error> {
+        let last = match self.history()?.pop() {
+            Some(activation) => activation,
+            None => return Ok(None),
+        };
+
+        let dir = self.root.join(&last.id);
+
+        for file in &last.files {
+            match file.backup {
+                Some(ref backup) => {
+                    fs::copy(dir.join(backup), &file.path)?;
+                }
+                None if file.path.exists() => fs::remove_file(&file.path)?,
+                None => {}
+            }
+        }
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(Some(last))
+    }
+}
+
+fn read_manifest(dir: &Path) -> Result<Activation, Error> {
+    let manifest = fs::read_to_string(dir.join(MANIFEST))?;
+
+    Ok(serde_json::from_str(&manifest)?)
+}
+
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_827_696), "2000-02-29 12:34:56 UTC");
+    }
+
+    #[test]
+    fn it_restores_the_last_activation() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(dir.path().join("backups"));
+        let config = dir.path().join("kitty.conf");
+
+        fs::write(&config, "color0 #000000").unwrap();
+        store
+            .snapshot("first", std::slice::from_ref(&config))
+            .unwrap();
+        fs::write(&config, "color0 #111111").unwrap();
+        store
+            .snapshot("second", std::slice::from_ref(&config))
+            .unwrap();
+        fs::write(&config, "color0 #222222").unwrap();
+
+        let themes: Vec<String> = store
+            .history()
+            .unwrap()
+            .into_iter()
+            .map(|a| a.theme)
+            .collect();
+        assert_eq!(themes, vec!["first", "second"]);
+
+        let undone = store.undo().unwrap().unwrap();
+        assert_eq!(undone.theme, "second");
+        assert_eq!(fs::read_to_string(&config).unwrap(), "color0 #111111");
+
+        store.undo().unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "color0 #000000");
+
+        assert_eq!(store.undo().unwrap(), None);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::apps::{Conversion, TerminalBackend};
+use crate::Theme;
+use failure::{err_msg, Error};
+use std::ops::Range;
+
+/// Where the block between `begin` and `end` starts and ends in `config`
+fn find_block(config: &str, begin: &str, end: &str) -> Result<Option<Range<usize>>, Error> {
+    let mut begin_at: Option<usize> = None;
+    let mut offset = 0;
+
+    for line in config.split_inclusive('\n') {
+        let trimmed = line.trim();
+
+        if begin_at.is_none() && trimmed == begin {
+            begin_at = Some(offset);
+        } else if begin_at.is_some() && trimmed == end {
+            let end_at = offset + line.trim_end_matches(&['\r', '\n'][..]).len();
+            return Ok(begin_at.map(|begin_at| begin_at..end_at));
+        }
+
+        offset += line.len();
+    }
+
+    match begin_at {
+        Some(_) => Err(err_msg(format!("Found '{}' but no '{}'", begin, end))),
+        None => Ok(None),
+    }
+}
+
+/// Finds the block of the app `name`, between `<comment> BEGIN teems <name>`
+/// and `<comment> END teems <name>`. Blocks written before the markers had
+/// the app name in them count as well.
+fn find_app_block(config: &str, comment: &str, name: &str) -> Result<Option<Range<usize>>, Error> {
+    let named = find_block(
+        config,
+        &format!("{} BEGIN teems {}", comment, name),
+        &format!("{} END teems {}", comment, name),
+    )?;
+
+    match named {
+        Some(range) => Ok(Some(range)),
+        None => find_block(
+            config,
+            &format!("{} BEGIN teems", comment),
+            &format!("{} END teems", comment),
+        ),
+    }
+}
+
+/// Replaces the block of the app `name` (see `find_app_block`) with
+/// `content`. Everything outside of the markers is kept byte for byte.
+/// Without markers the block is appended to the end of `config`.
+pub fn replace_block(
+    config: &str,
+    comment: &str,
+    name: &str,
+    content: &str,
+) -> Result<String, Error> {
+    let begin = format!("{} BEGIN teems {}", comment, name);
+    let end = format!("{} END teems {}", comment, name);
+
+    let block = if content.is_empty() {
+        format!("{}\n{}", begin, end)
+    } else {
+        format!("{}\n{}\n{}", begin, content.trim_end_matches('\n'), end)
+    };
+
+    match find_app_block(config, comment, name)? {
+        Some(range) => Ok(format!(
+            "{}{}{}",
+            &config[..range.start],
+            block,
+            &config[range.end..]
+        )),
+        None => {
+            let mut result = config.to_owned();
+
+            if !result.is_empty() {
+                if !result.ends_with('\n') {
+                    result.push('\n');
+                }
+                result.push('\n');
+            }
+            result.push_str(&block);
+            result.push('\n');
+
+            Ok(result)
+        }
+    }
+}
+
+/// Regenerates the teems block in `config` from `theme`, using the colors
+/// the backend renders for a fresh config
+pub fn apply(
+    backend: &dyn TerminalBackend,
+    theme: &Theme,
+    config: &str,
+) -> Result<Conversion, Error> {
+    if find_app_block(config, backend.comment(), backend.name())?.is_none() {
+        backend.check_new_block(config)?;
+    }
+
+    let rendered = backend.render(theme)?;
+
+    Ok(Conversion {
+        config: replace_block(config, backend.comment(), backend.name(), &rendered.config)?,
+        ..rendered
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replaces_the_block() {
+        let cfg = "font_size 12\r\n# BEGIN teems kitty\ncolor0 #ffffff\ncolor1 #ffffff\n# END teems kitty\r\ncursor_blink 0";
+
+        let expected =
+            "font_size 12\r\n# BEGIN teems kitty\ncolor0 #000000\n# END teems kitty\r\ncursor_blink 0";
+
+        assert_eq!(
+            replace_block(cfg, "#", "kitty", "color0 #000000").unwrap(),
+            expected
+        );
+
+        // Blocks from before the markers had the app name
+        assert_eq!(
+            replace_block(
+                "# BEGIN teems\ncolor0 #ffffff\n# END teems",
+                "#",
+                "kitty",
+                ""
+            )
+            .unwrap(),
+            "# BEGIN teems kitty\n# END teems kitty"
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_blocks_of_apps_sharing_a_file() {
+        let cfg = replace_block("", "!", "x", "*.color0: #000000").unwrap();
+        let cfg = replace_block(&cfg, "!", "xterm", "XTerm*color0: #000000").unwrap();
+        let cfg = replace_block(&cfg, "!", "x", "*.color0: #111111").unwrap();
+
+        assert_eq!(
+            cfg,
+            "! BEGIN teems x\n*.color0: #111111\n! END teems x\n\n! BEGIN teems xterm\nXTerm*color0: #000000\n! END teems xterm\n"
+        );
+    }
+
+    #[test]
+    fn it_appends_a_block() {
+        assert_eq!(
+            replace_block("URxvt.font: xft:Monospace", "!", "x", "*.color0: #000000\n").unwrap(),
+            "URxvt.font: xft:Monospace\n\n! BEGIN teems x\n*.color0: #000000\n! END teems x\n"
+        );
+        assert_eq!(
+            replace_block("", "#", "kitty", "color0 #000000").unwrap(),
+            "# BEGIN teems kitty\ncolor0 #000000\n# END teems kitty\n"
+        );
+    }
+
+    #[test]
+    fn it_fails_if_end_marker_is_missing() {
+        let cfg = "# BEGIN teems kitty\ncolor0 #ffffff\n";
+
+        assert!(replace_block(cfg, "#", "kitty", "color0 #000000").is_err());
+    }
+}
@@ -0,0 +1,556 @@
+//! Conversions between `RGBA` (sRGB) and other color spaces, and operations
+//! built on them. All components are `f64`, hues are in degrees.
+
+use crate::RGBA;
+
+/// Hue, saturation and lightness, saturation and lightness from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsl {
+    pub h: f64,
+    pub s: f64,
+    pub l: f64,
+}
+
+/// Hue, saturation and value, saturation and value from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    pub h: f64,
+    pub s: f64,
+    pub v: f64,
+}
+
+/// sRGB without the gamma curve, components from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearRgb {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+/// Display P3, the color space of Apple displays, components from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayP3 {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+/// CIE 1976 L*a*b* with a D65 white point, `l` from 0 to 100
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// Björn Ottosson's OKLab, `l` from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// OKLab in polar coordinates: lightness, chroma and hue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklch {
+    pub l: f64,
+    pub c: f64,
+    pub h: f64,
+}
+
+fn to_channel(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn to_linear(channel: u8) -> f64 {
+    decode(f64::from(channel) / 255.0)
+}
+
+/// The sRGB transfer curve, also used by Display P3
+fn decode(c: f64) -> f64 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f64) -> f64 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn normalize_hue(h: f64) -> f64 {
+    h.rem_euclid(360.0)
+}
+
+/// Hue and the min and max of the channels, shared by HSL and HSV
+fn hue_min_max(color: &RGBA) -> (f64, f64, f64) {
+    let (r, g, b) = (
+        f64::from(color.0) / 255.0,
+        f64::from(color.1) / 255.0,
+        f64::from(color.2) / 255.0,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (normalize_hue(h), min, max)
+}
+
+// D65 reference white for CIELAB
+const WHITE: (f64, f64, f64) = (0.950_47, 1.0, 1.088_83);
+
+impl RGBA {
+    pub fn new(r: u8, g: u8, b: u8, alpha: f32) -> RGBA {
+        RGBA(r, g, b, alpha)
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.3
+    }
+
+    pub fn to_linear(&self) -> LinearRgb {
+        LinearRgb {
+            r: to_linear(self.0),
+            g: to_linear(self.1),
+            b: to_linear(self.2),
+        }
+    }
+
+    pub fn from_linear(c: LinearRgb, alpha: f32) -> RGBA {
+        RGBA(
+            to_channel(from_linear(c.r)),
+            to_channel(from_linear(c.g)),
+            to_channel(from_linear(c.b)),
+            alpha,
+        )
+    }
+
+    /// Colors outside of sRGB are clipped
+    pub fn from_display_p3(c: DisplayP3, alpha: f32) -> RGBA {
+        let (r, g, b) = (decode(c.r), decode(c.g), decode(c.b));
+
+        RGBA::from_linear(
+            LinearRgb {
+                r: 1.224_940_2 * r - 0.224_940_2 * g,
+                g: -0.042_056_95 * r + 1.042_056_95 * g,
+                b: -0.019_637_55 * r - 0.078_636_05 * g + 1.098_273_6 * b,
+            },
+            alpha,
+        )
+    }
+
+    pub fn to_hsl(&self) -> Hsl {
+        let (h, min, max) = hue_min_max(self);
+        let l = (max + min) / 2.0;
+
+        let s = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * l - 1.0).abs())
+        };
+
+        Hsl { h, s, l }
+    }
+
+    pub fn from_hsl(c: Hsl, alpha: f32) -> RGBA {
+        let chroma = (1.0 - (2.0 * c.l - 1.0).abs()) * c.s;
+        let m = c.l - chroma / 2.0;
+
+        RGBA::from_hue_chroma(c.h, chroma, m, alpha)
+    }
+
+    pub fn to_hsv(&self) -> Hsv {
+        let (h, min, max) = hue_min_max(self);
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+        Hsv { h, s, v: max }
+    }
+
+    pub fn from_hsv(c: Hsv, alpha: f32) -> RGBA {
+        let chroma = c.v * c.s;
+
+        RGBA::from_hue_chroma(c.h, chroma, c.v - chroma, alpha)
+    }
+
+    fn from_hue_chroma(hue: f64, chroma: f64, m: f64, alpha: f32) -> RGBA {
+        let h = normalize_hue(hue) / 60.0;
+        let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        RGBA(
+            to_channel(r + m),
+            to_channel(g + m),
+            to_channel(b + m),
+            alpha,
+        )
+    }
+
+    pub fn to_lab(&self) -> Lab {
+        let LinearRgb { r, g, b } = self.to_linear();
+
+        let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b;
+
+        let f = |t: f64| {
+            if t > 216.0 / 24389.0 {
+                t.cbrt()
+            } else {
+                (24389.0 / 27.0 * t + 16.0) / 116.0
+            }
+        };
+
+        let (fx, fy, fz) = (f(x / WHITE.0), f(y / WHITE.1), f(z / WHITE.2));
+
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+
+    pub fn from_lab(c: Lab, alpha: f32) -> RGBA {
+        let fy = (c.l + 16.0) / 116.0;
+        let fx = fy + c.a / 500.0;
+        let fz = fy - c.b / 200.0;
+
+        let f_inv = |t: f64| {
+            if t.powi(3) > 216.0 / 24389.0 {
+                t.powi(3)
+            } else {
+                (116.0 * t - 16.0) * 27.0 / 24389.0
+            }
+        };
+
+        let (x, y, z) = (
+            f_inv(fx) * WHITE.0,
+            f_inv(fy) * WHITE.1,
+            f_inv(fz) * WHITE.2,
+        );
+
+        RGBA::from_linear(
+            LinearRgb {
+                r: 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+                g: -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+                b: 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+            },
+            alpha,
+        )
+    }
+
+    pub fn to_oklab(&self) -> Oklab {
+        linear_to_oklab(self.to_linear())
+    }
+
+    pub fn from_oklab(c: Oklab, alpha: f32) -> RGBA {
+        RGBA::from_linear(oklab_to_linear(c), alpha)
+    }
+
+    pub fn to_oklch(&self) -> Oklch {
+        let Oklab { l, a, b } = self.to_oklab();
+
+        Oklch {
+            l,
+            c: a.hypot(b),
+            h: normalize_hue(b.atan2(a).to_degrees()),
+        }
+    }
+
+    /// Colors outside of sRGB lose chroma until they fit, so lightness and
+    /// hue are kept
+    pub fn from_oklch(c: Oklch, alpha: f32) -> RGBA {
+        let to_linear = |chroma: f64| {
+            let h = c.h.to_radians();
+
+            oklab_to_linear(Oklab {
+                l: c.l,
+                a: chroma * h.cos(),
+                b: chroma * h.sin(),
+            })
+        };
+
+        let in_gamut = |lin: &LinearRgb| {
+            let ok = |v: f64| (-1e-4..=1.0 + 1e-4).contains(&v);
+            ok(lin.r) && ok(lin.g) && ok(lin.b)
+        };
+
+        let mut linear = to_linear(c.c);
+
+        if !in_gamut(&linear) {
+            let (mut low, mut high) = (0.0, c.c);
+
+            for _ in 0..24 {
+                let mid = (low + high) / 2.0;
+
+                if in_gamut(&to_linear(mid)) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+
+            linear = to_linear(low);
+        }
+
+        RGBA::from_linear(linear, alpha)
+    }
+
+    /// Raises the OKLCH lightness by `amount`, from 0 to 1
+    pub fn lighten(&self, amount: f64) -> RGBA {
+        let mut c = self.to_oklch();
+        c.l = (c.l + amount).clamp(0.0, 1.0);
+
+        RGBA::from_oklch(c, self.3)
+    }
+
+    /// Lowers the OKLCH lightness by `amount`, from 0 to 1
+    pub fn darken(&self, amount: f64) -> RGBA {
+        self.lighten(-amount)
+    }
+
+    /// Scales the OKLCH chroma by `1 + amount`, so a negative `amount`
+    /// desaturates and -1 gives a gray of the same lightness
+    pub fn saturate(&self, amount: f64) -> RGBA {
+        let mut c = self.to_oklch();
+        c.c = (c.c * (1.0 + amount)).max(0.0);
+
+        RGBA::from_oklch(c, self.3)
+    }
+
+    /// Interpolates in OKLab between this color (`t` = 0) and `other`
+    /// (`t` = 1)
+    pub fn mix(&self, other: &RGBA, t: f64) -> RGBA {
+        let (a, b) = (self.to_oklab(), other.to_oklab());
+        let lerp = |x: f64, y: f64| x + (y - x) * t;
+
+        RGBA::from_oklab(
+            Oklab {
+                l: lerp(a.l, b.l),
+                a: lerp(a.a, b.a),
+                b: lerp(a.b, b.b),
+            },
+            lerp(f64::from(self.3), f64::from(other.3)) as f32,
+        )
+    }
+
+    /// The CIEDE2000 color difference. Around 1 is barely noticeable,
+    /// above 10 the colors look clearly different.
+    pub fn delta_e(&self, other: &RGBA) -> f64 {
+        delta_e_2000(self.to_lab(), other.to_lab())
+    }
+}
+
+fn linear_to_oklab(c: LinearRgb) -> Oklab {
+    let l = (0.412_221_470_8 * c.r + 0.536_332_536_3 * c.g + 0.051_445_992_9 * c.b).cbrt();
+    let m = (0.211_903_498_2 * c.r + 0.680_699_545_1 * c.g + 0.107_396_956_6 * c.b).cbrt();
+    let s = (0.088_302_461_9 * c.r + 0.281_718_837_6 * c.g + 0.629_978_700_5 * c.b).cbrt();
+
+    Oklab {
+        l: 0.210_454_255_3 * l + 0.793_617_785 * m - 0.004_072_046_8 * s,
+        a: 1.977_998_495_1 * l - 2.428_592_205 * m + 0.450_593_709_9 * s,
+        b: 0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766 * s,
+    }
+}
+
+fn oklab_to_linear(c: Oklab) -> LinearRgb {
+    let l = (c.l + 0.396_337_777_4 * c.a + 0.215_803_757_3 * c.b).powi(3);
+    let m = (c.l - 0.105_561_345_8 * c.a - 0.063_854_172_8 * c.b).powi(3);
+    let s = (c.l - 0.089_484_177_5 * c.a - 1.291_485_548 * c.b).powi(3);
+
+    LinearRgb {
+        r: 4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s,
+        g: -1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s,
+        b: -0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701 * s,
+    }
+}
+
+/// See Sharma, Wu and Dalal, "The CIEDE2000 Color-Difference Formula"
+pub fn delta_e_2000(x: Lab, y: Lab) -> f64 {
+    let c_bar = (x.a.hypot(x.b) + y.a.hypot(y.b)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt());
+
+    let a1 = (1.0 + g) * x.a;
+    let a2 = (1.0 + g) * y.a;
+    let c1 = a1.hypot(x.b);
+    let c2 = a2.hypot(y.b);
+
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            normalize_hue(b.atan2(a).to_degrees())
+        }
+    };
+    let h1 = hue(a1, x.b);
+    let h2 = hue(a2, y.b);
+
+    let dl = y.l - x.l;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_bar = (x.l + y.l) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+
+    let d_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt();
+    let sl = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * c_bar;
+    let sh = 1.0 + 0.015 * c_bar * t;
+    let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+    ((dl / sl).powi(2) + (dc / sc).powi(2) + (dh / sh).powi(2) + rt * (dc / sc) * (dh / sh)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn it_converts_to_and_from_hsl_and_hsv() {
+        let orange = RGBA(255, 128, 0, 1.0);
+
+        let hsl = orange.to_hsl();
+        assert!(close(hsl.h, 30.117) && close(hsl.s, 1.0) && close(hsl.l, 0.5));
+        assert_eq!(RGBA::from_hsl(hsl, 1.0), orange);
+
+        let hsv = orange.to_hsv();
+        assert!(close(hsv.s, 1.0) && close(hsv.v, 1.0));
+        assert_eq!(RGBA::from_hsv(hsv, 1.0), orange);
+    }
+
+    #[test]
+    fn it_converts_to_and_from_lab_and_oklab() {
+        let red = RGBA(255, 0, 0, 1.0);
+
+        let lab = red.to_lab();
+        assert!((lab.l - 53.24).abs() < 0.01);
+        assert!((lab.a - 80.09).abs() < 0.01);
+        assert!((lab.b - 67.20).abs() < 0.01);
+        assert_eq!(RGBA::from_lab(lab, 1.0), red);
+
+        let oklab = red.to_oklab();
+        assert!(close(oklab.l, 0.628) && close(oklab.a, 0.2249) && close(oklab.b, 0.1258));
+
+        let teal = RGBA(23, 140, 139, 0.5);
+        assert_eq!(RGBA::from_oklab(teal.to_oklab(), 0.5), teal);
+        assert_eq!(RGBA::from_oklch(teal.to_oklch(), 0.5), teal);
+    }
+
+    #[test]
+    fn it_keeps_lightness_and_hue_when_out_of_gamut() {
+        let color = RGBA::from_oklch(
+            Oklch {
+                l: 0.7,
+                c: 0.4,
+                h: 150.0,
+            },
+            1.0,
+        );
+
+        let oklch = color.to_oklch();
+        assert!((oklch.l - 0.7).abs() < 0.01);
+        assert!((oklch.h - 150.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn it_lightens_saturates_and_mixes() {
+        let gray = RGBA(128, 128, 128, 1.0);
+
+        assert!(gray.lighten(0.1).to_oklch().l > gray.to_oklch().l + 0.09);
+        assert!(gray.darken(0.1).to_oklch().l < gray.to_oklch().l - 0.09);
+
+        let red = RGBA(200, 50, 50, 1.0);
+        assert!(red.saturate(-0.5).to_oklch().c < red.to_oklch().c);
+        assert_eq!(red.saturate(-1.0).to_hsl().s, 0.0);
+
+        let black = RGBA(0, 0, 0, 1.0);
+        let white = RGBA(255, 255, 255, 1.0);
+        assert_eq!(black.mix(&white, 0.0), black);
+        assert_eq!(black.mix(&white, 1.0), white);
+        assert!(close(black.mix(&white, 0.5).to_oklab().l, 0.5));
+    }
+
+    #[test]
+    fn it_computes_delta_e_2000() {
+        // Test pairs from Sharma et al.
+        let pairs = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            (
+                (60.2574, -34.0099, 36.2677),
+                (60.4626, -34.1751, 39.4387),
+                1.2644,
+            ),
+            ((2.0776, 0.0795, -1.135), (0.9033, -0.0636, -0.5514), 0.9082),
+        ];
+
+        for ((l1, a1, b1), (l2, a2, b2), expected) in &pairs {
+            let x = Lab {
+                l: *l1,
+                a: *a1,
+                b: *b1,
+            };
+            let y = Lab {
+                l: *l2,
+                a: *a2,
+                b: *b2,
+            };
+
+            assert!((delta_e_2000(x, y) - expected).abs() < 1e-4);
+        }
+
+        let red = RGBA(255, 0, 0, 1.0);
+        assert_eq!(red.delta_e(&red), 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::palette::Palette;
+use crate::transaction;
+use crate::{ColorName, RGBA};
+use failure::{err_msg, Error};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// The theme file as plain JSON. Teems edits it through this, so anything it
+/// doesn't touch, like other themes or keys it doesn't know, stays as it is.
+pub struct ThemeFile {
+    pub themes: Vec<Value>,
+}
+
+impl ThemeFile {
+    /// Reads the themes in `path`. A file that doesn't exist yet has none.
+    pub fn load(path: &Path) -> Result<ThemeFile, Error> {
+        if !path.exists() {
+            return Ok(ThemeFile { themes: vec![] });
+        }
+
+        let contents = fs::read_to_string(path)?;
+
+        match serde_json::from_str(&contents)? {
+            Value::Array(themes) => Ok(ThemeFile { themes }),
+            _ => Err(err_msg(format!(
+                "{} should contain a list of themes",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut contents = serde_json::to_string_pretty(&self.themes)?;
+        contents.push('\n');
+
+        transaction::write_atomic(path, &contents)
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.themes
+            .iter()
+            .position(|t| t.get("name").and_then(Value::as_str) == Some(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        let position = self.position(name)?;
+
+        self.themes.get_mut(position)
+    }
+
+    /// Adds `theme`, failing if there is a theme with the same name already
+    pub fn append(&mut self, theme: Value) -> Result<(), Error> {
+        let name = theme
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| err_msg("Theme has no name"))?;
+
+        if self.position(name).is_some() {
+            return Err(err_msg(format!("There already is a theme called {}", name)));
+        }
+
+        self.themes.push(theme);
+
+        Ok(())
+    }
+}
+
+/// Sets `colors` in the theme object `theme`. A color the theme already has
+/// under another name, like `text` for `cursor_text`, keeps that name.
+pub fn set_colors(theme: &mut Value, colors: &[(ColorName, RGBA)]) -> Result<(), Error> {
+    let map = theme
+        .as_object_mut()
+        .ok_or_else(|| err_msg("Theme is not an object"))?
+        .entry("colors")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| err_msg("Theme colors are not an object"))?;
+
+    for (name, color) in colors {
+        let key = map
+            .keys()
+            .find(|k| Palette::canonical_name(k) == name)
+            .cloned()
+            .unwrap_or_else(|| name.clone());
+
+        map.insert(key, Value::String(color.to_css_hex()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_updates_colors_and_keeps_everything_else() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("themes.json");
+        fs::write(
+            &path,
+            r##"[{"name": "foo", "author": "me", "colors": {"text": "#000000", "color1": "red"}}]"##,
+        )
+        .unwrap();
+
+        let mut file = ThemeFile::load(&path).unwrap();
+
+        let mut copy = file.themes[0].clone();
+        copy["name"] = json!("bar");
+        set_colors(
+            &mut copy,
+            &[
+                (String::from("cursor_text"), RGBA(1, 2, 3, 1.0)),
+                (String::from("color2"), RGBA(0, 0, 0, 0.5)),
+            ],
+        )
+        .unwrap();
+
+        file.append(copy).unwrap();
+        assert!(file.append(json!({"name": "foo"})).is_err());
+        file.save(&path).unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            json!([
+                {"name": "foo", "author": "me", "colors": {"text": "#000000", "color1": "red"}},
+                {
+                    "name": "bar",
+                    "author": "me",
+                    "colors": {"text": "#010203", "color1": "red", "color2": "#00000080"}
+                }
+            ])
+        );
+    }
+}
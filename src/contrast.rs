@@ -0,0 +1,446 @@
+use crate::color::Oklch;
+use crate::{ColorName, Theme, RGBA};
+use failure::{err_msg, Error};
+use std::fmt;
+
+/// WCAG 2.x relative luminance
+pub fn relative_luminance(color: &RGBA) -> f64 {
+    let c = color.to_linear();
+
+    0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b
+}
+
+/// WCAG 2.x contrast ratio, from 1 (none) to 21 (black on white)
+pub fn wcag_ratio(a: &RGBA, b: &RGBA) -> f64 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// APCA lightness contrast (Lc) of `text` on `background`, version
+/// 0.0.98G-4g. Positive for dark text on a light background, negative for
+/// light text on a dark one. Around 60 is fine for body text, 75 is better.
+pub fn apca_lc(text: &RGBA, background: &RGBA) -> f64 {
+    let luminance = |c: &RGBA| {
+        let y = 0.212_672_9 * (f64::from(c.0) / 255.0).powf(2.4)
+            + 0.715_152_2 * (f64::from(c.1) / 255.0).powf(2.4)
+            + 0.072_175 * (f64::from(c.2) / 255.0).powf(2.4);
+
+        // Soft clamp for very dark colors
+        if y < 0.022 {
+            y + (0.022 - y).powf(1.414)
+        } else {
+            y
+        }
+    };
+
+    let (y_text, y_background) = (luminance(text), luminance(background));
+
+    if (y_background - y_text).abs() < 0.0005 {
+        return 0.0;
+    }
+
+    let lc = if y_background > y_text {
+        let sapc = (y_background.powf(0.56) - y_text.powf(0.57)) * 1.14;
+
+        if sapc < 0.1 {
+            0.0
+        } else {
+            sapc - 0.027
+        }
+    } else {
+        let sapc = (y_background.powf(0.65) - y_text.powf(0.62)) * 1.14;
+
+        if sapc > -0.1 {
+            0.0
+        } else {
+            sapc + 0.027
+        }
+    };
+
+    lc * 100.0
+}
+
+/// The contrast of one theme color against another
+#[derive(Debug, PartialEq)]
+pub struct Contrast {
+    pub foreground: ColorName,
+    pub background: ColorName,
+    pub ratio: f64,
+    pub lc: f64,
+}
+
+/// Minimum contrast every checked pair needs
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    /// WCAG contrast ratio, 4.5 is AA and 7 is AAA for normal text
+    pub min_ratio: f64,
+    /// Absolute APCA Lc, not checked if `None`
+    pub min_lc: Option<f64>,
+    /// Colors that are not checked, e.g., `color0` which is often meant to
+    /// be close to the background
+    pub ignore: Vec<ColorName>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            min_ratio: 4.5,
+            min_lc: None,
+            ignore: vec![],
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn passes(&self, contrast: &Contrast) -> bool {
+        contrast.ratio >= self.min_ratio && self.min_lc.is_none_or(|min| contrast.lc.abs() >= min)
+    }
+}
+
+/// Foreground and background color names of every pair that is checked:
+/// the ANSI colors, foreground and cursor against the background, and the
+/// cursor and selection text against their own backgrounds.
+fn pairs() -> Vec<(String, &'static str)> {
+    let mut pairs: Vec<(String, &str)> = (0..16)
+        .map(|i| (format!("color{}", i), "background"))
+        .collect();
+
+    pairs.push((String::from("foreground"), "background"));
+    pairs.push((String::from("cursor"), "background"));
+    pairs.push((String::from("cursor_text"), "cursor"));
+    pairs.push((String::from("selection_foreground"), "selection_background"));
+
+    pairs
+}
+
+/// The contrast of every pair `theme` has both colors for
+pub fn check(theme: &Theme) -> Result<ContrastReport, Error> {
+    if !theme.colors.contains("background") {
+        return Err(err_msg(format!(
+            "Theme {} has no background color to check against",
+            theme.name
+        )));
+    }
+
+    let contrasts = pairs()
+        .into_iter()
+        .filter_map(|(fg, bg)| {
+            let (fg_color, bg_color) = (theme.colors.get(&fg)?, theme.colors.get(bg)?);
+
+            Some(Contrast {
+                ratio: wcag_ratio(fg_color, bg_color),
+                lc: apca_lc(fg_color, bg_color),
+                foreground: fg,
+                background: bg.to_owned(),
+            })
+        })
+        .collect();
+
+    Ok(ContrastReport {
+        theme: theme.name.clone(),
+        contrasts,
+        thresholds: Thresholds::default(),
+    })
+}
+
+/// `color` with its OKLCH lightness changed as little as possible to get a
+/// contrast ratio of at least `target` against `background`. Chroma and hue
+/// stay the same where sRGB allows. `None` if no lightness is enough.
+pub fn fix_color(color: &RGBA, background: &RGBA, target: f64) -> Option<RGBA> {
+    if wcag_ratio(color, background) >= target {
+        return Some(*color);
+    }
+
+    let start = color.to_oklch();
+    let with_lightness = |l: f64| RGBA::from_oklch(Oklch { l, ..start }, color.alpha());
+
+    // Moving away from the background's lightness first, since that is
+    // where the contrast is
+    let lighter_first = relative_luminance(color) >= relative_luminance(background);
+    let ends = if lighter_first {
+        [1.0, 0.0]
+    } else {
+        [0.0, 1.0]
+    };
+
+    ends.iter().find_map(|&end| {
+        if wcag_ratio(&with_lightness(end), background) < target {
+            return None;
+        }
+
+        // The lightness closest to the start that still passes
+        let (mut near, mut far) = (start.l, end);
+        for _ in 0..32 {
+            let mid = (near + far) / 2.0;
+
+            if wcag_ratio(&with_lightness(mid), background) >= target {
+                far = mid;
+            } else {
+                near = mid;
+            }
+        }
+
+        Some(with_lightness(far))
+    })
+}
+
+/// A theme color that `fix_contrast` changed
+#[derive(Debug, PartialEq)]
+pub struct Fix {
+    pub name: ColorName,
+    pub against: ColorName,
+    pub before: RGBA,
+    pub after: RGBA,
+    pub ratio_before: f64,
+    pub ratio_after: f64,
+}
+
+/// Adjusts every color of `theme` that is below `thresholds.min_ratio`
+/// against its background. Returns the changes and the colors that can't
+/// reach the ratio.
+///
+/// The pairs are fixed one after another, each against the colors as the
+/// fixes before it left them: `cursor_text` is checked against the fixed
+/// `cursor`.
+pub fn fix_contrast(
+    theme: &Theme,
+    thresholds: &Thresholds,
+) -> Result<(Vec<Fix>, Vec<ColorName>), Error> {
+    check(theme)?;
+
+    let mut fixed = Theme {
+        name: theme.name.clone(),
+        extends: theme.extends.clone(),
+        colors: theme.colors.clone(),
+    };
+    let mut fixes = vec![];
+    let mut unfixable = vec![];
+
+    for (name, against) in pairs() {
+        if thresholds.ignore.contains(&name) {
+            continue;
+        }
+
+        let (before, background) = match (fixed.colors.get(&name), fixed.colors.get(against)) {
+            (Some(before), Some(background)) => (*before, *background),
+            _ => continue,
+        };
+
+        let ratio_before = wcag_ratio(&before, &background);
+        if ratio_before >= thresholds.min_ratio {
+            continue;
+        }
+
+        match fix_color(&before, &background, thresholds.min_ratio) {
+            Some(after) => {
+                fixed.colors.insert(&name, after);
+                fixes.push(Fix {
+                    ratio_after: wcag_ratio(&after, &background),
+                    ratio_before,
+                    name,
+                    against: against.to_owned(),
+                    before,
+                    after,
+                });
+            }
+            None => unfixable.push(name),
+        }
+    }
+
+    // Whatever still fails in the end can't be fixed, even if its own fix
+    // went through
+    for contrast in check(&fixed)?.contrasts {
+        if !thresholds.ignore.contains(&contrast.foreground)
+            && contrast.ratio < thresholds.min_ratio
+            && !unfixable.contains(&contrast.foreground)
+        {
+            unfixable.push(contrast.foreground);
+        }
+    }
+
+    Ok((fixes, unfixable))
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<22}{:<11}{:>6.2}  ->  {:<11}{:>6.2}",
+            self.name,
+            self.before.to_css_hex(),
+            self.ratio_before,
+            self.after.to_css_hex(),
+            self.ratio_after
+        )
+    }
+}
+
+pub struct ContrastReport {
+    pub theme: String,
+    pub contrasts: Vec<Contrast>,
+    pub thresholds: Thresholds,
+}
+
+impl ContrastReport {
+    /// The checked pairs that don't pass the thresholds
+    pub fn failures(&self) -> Vec<&Contrast> {
+        self.contrasts
+            .iter()
+            .filter(|c| !self.thresholds.ignore.contains(&c.foreground))
+            .filter(|c| !self.thresholds.passes(c))
+            .collect()
+    }
+}
+
+impl fmt::Display for ContrastReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pass = |ok: bool| if ok { "pass" } else { "fail" };
+
+        writeln!(f, "{}", self.theme)?;
+        writeln!(
+            f,
+            "  {:<22}{:<22}{:>7}{:>8}  {:<6}AAA",
+            "color", "against", "ratio", "Lc", "AA"
+        )?;
+
+        for c in &self.contrasts {
+            let status = if self.thresholds.ignore.contains(&c.foreground) {
+                "  (ignored)"
+            } else if self.thresholds.passes(c) {
+                ""
+            } else {
+                "  <- below threshold"
+            };
+
+            let line = format!(
+                "  {:<22}{:<22}{:>7.2}{:>8.1}  {:<6}{:<6}{}",
+                c.foreground,
+                c.background,
+                c.ratio,
+                c.lc,
+                pass(c.ratio >= 4.5),
+                pass(c.ratio >= 7.0),
+                status
+            );
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_wcag_ratios() {
+        let black = RGBA(0, 0, 0, 1.0);
+        let white = RGBA(255, 255, 255, 1.0);
+
+        assert!((wcag_ratio(&black, &white) - 21.0).abs() < 1e-9);
+        assert!((wcag_ratio(&white, &black) - 21.0).abs() < 1e-9);
+        assert!((wcag_ratio(&RGBA(119, 119, 119, 1.0), &white) - 4.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn it_computes_apca_lc() {
+        let black = RGBA(0, 0, 0, 1.0);
+        let white = RGBA(255, 255, 255, 1.0);
+        let gray = RGBA(136, 136, 136, 1.0);
+
+        assert!((apca_lc(&black, &white) - 106.04).abs() < 0.01);
+        assert!((apca_lc(&white, &black) + 107.88).abs() < 0.01);
+        assert!((apca_lc(&gray, &white) - 63.06).abs() < 0.01);
+        assert!((apca_lc(&white, &gray) + 68.54).abs() < 0.01);
+        assert_eq!(apca_lc(&gray, &gray), 0.0);
+    }
+
+    #[test]
+    fn it_reports_pairs_below_the_threshold() {
+        let colors = vec![
+            (String::from("background"), RGBA(0, 0, 0, 1.0)),
+            (String::from("foreground"), RGBA(255, 255, 255, 1.0)),
+            (String::from("color0"), RGBA(10, 10, 10, 1.0)),
+            (String::from("color1"), RGBA(60, 60, 60, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+
+        let mut report = check(&theme).unwrap();
+        assert_eq!(report.contrasts.len(), 3);
+
+        let failures: Vec<&str> = report
+            .failures()
+            .iter()
+            .map(|c| c.foreground.as_str())
+            .collect();
+        assert_eq!(failures, vec!["color0", "color1"]);
+
+        report.thresholds.ignore = vec![String::from("color0")];
+        report.thresholds.min_ratio = 1.5;
+        assert!(report.failures().is_empty());
+
+        report.thresholds.min_lc = Some(60.0);
+        assert_eq!(report.failures().len(), 1);
+    }
+
+    #[test]
+    fn it_fixes_contrast_keeping_the_hue() {
+        let background = RGBA(29, 31, 33, 1.0);
+        let red = RGBA(160, 60, 60, 1.0);
+
+        let fixed = fix_color(&red, &background, 4.5).unwrap();
+
+        assert!(wcag_ratio(&fixed, &background) >= 4.5);
+        assert!(fixed.to_oklch().l > red.to_oklch().l);
+        assert!((fixed.to_oklch().h - red.to_oklch().h).abs() < 2.0);
+        // No bigger change than needed
+        assert!(wcag_ratio(&fixed, &background) < 4.7);
+
+        // Light backgrounds make colors darker
+        let white = RGBA(255, 255, 255, 1.0);
+        let yellow = RGBA(230, 200, 40, 1.0);
+        let fixed = fix_color(&yellow, &white, 4.5).unwrap();
+        assert!(fixed.to_oklch().l < yellow.to_oklch().l);
+
+        assert_eq!(fix_color(&red, &background, 22.0), None);
+    }
+
+    #[test]
+    fn it_fixes_pairs_against_fixed_colors() {
+        let colors = vec![
+            (String::from("background"), RGBA(0, 0, 0, 1.0)),
+            (String::from("cursor"), RGBA(51, 51, 51, 1.0)),
+            (String::from("cursor_text"), RGBA(32, 32, 32, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+
+        let (fixes, unfixable) = fix_contrast(&theme, &Thresholds::default()).unwrap();
+        let names: Vec<&str> = fixes.iter().map(|f| f.name.as_str()).collect();
+
+        assert_eq!(names, vec!["cursor", "cursor_text"]);
+        assert!(unfixable.is_empty());
+
+        let mut fixed = theme;
+        for fix in &fixes {
+            fixed.colors.insert(&fix.name, fix.after);
+        }
+        for contrast in check(&fixed).unwrap().contrasts {
+            assert!(contrast.ratio >= 4.5, "{:?}", contrast);
+        }
+    }
+}
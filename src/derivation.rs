@@ -0,0 +1,174 @@
+use crate::{Theme, RGBA};
+
+/// The xterm color cube levels, used when a theme lacks one of the colors a
+/// cube corner is derived from
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Fills the colors a theme doesn't set from the ones it does:
+///
+/// * `color8` to `color15` by lightening `color0` to `color7`
+/// * `dim_color0` to `dim_color7`, alacritty's dim colors, by darkening them
+/// * `color16` to `color231` as a color cube between the eight normal colors
+///   (with the background and foreground as its black and white corners),
+///   and `color232` to `color255` as a ramp from background to foreground
+///
+/// Colors set by the theme are kept. The names of the derived colors end up
+/// in `colors.derived`.
+pub fn derive(theme: &Theme) -> Theme {
+    let mut colors = theme.colors.clone();
+    let normal: Vec<Option<RGBA>> = (0..8).map(|i| colors.ansi[i]).collect();
+
+    let mut fill = |name: String, color: RGBA| {
+        if !colors.contains(&name) {
+            colors.insert(&name, color);
+            colors.derived.insert(name);
+        }
+    };
+
+    for (i, color) in normal.iter().enumerate() {
+        if let Some(color) = color {
+            fill(format!("color{}", i + 8), bright(*color));
+            fill(format!("dim_color{}", i), dim(*color));
+        }
+    }
+
+    let black = theme.colors.background.or(normal[0]);
+    let white = theme.colors.foreground.or(normal[7]);
+
+    let corners: Option<Vec<RGBA>> = (0..8)
+        .map(|i| match i {
+            0 => black,
+            7 => white,
+            _ => normal[i],
+        })
+        .collect();
+
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+
+        let color = match &corners {
+            Some(corners) => cube(corners, r, g, b),
+            None => RGBA(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b], 1.0),
+        };
+
+        fill(format!("color{}", 16 + i), color);
+    }
+
+    for i in 0..24 {
+        let color = match (black, white) {
+            (Some(black), Some(white)) => black.mix(&white, (i + 1) as f64 / 25.0),
+            _ => {
+                let level = 8 + 10 * i as u8;
+                RGBA(level, level, level, 1.0)
+            }
+        };
+
+        fill(format!("color{}", 232 + i), color);
+    }
+
+    Theme {
+        name: theme.name.clone(),
+        extends: theme.extends.clone(),
+        colors,
+    }
+}
+
+/// `theme` without the derived colors `keep` rejects, for an app that has
+/// no key for them
+pub fn restrict(theme: &Theme, keep: impl Fn(&str) -> bool) -> Theme {
+    let mut colors = theme.colors.clone();
+    let dropped: Vec<String> = colors
+        .derived
+        .iter()
+        .filter(|name| !keep(name))
+        .cloned()
+        .collect();
+
+    for name in dropped {
+        colors.remove(&name);
+        colors.derived.remove(&name);
+    }
+
+    Theme {
+        name: theme.name.clone(),
+        extends: theme.extends.clone(),
+        colors,
+    }
+}
+
+/// A quarter of the way to white, in OKLCH lightness
+fn bright(color: RGBA) -> RGBA {
+    color.lighten((1.0 - color.to_oklch().l) / 4.0)
+}
+
+/// A quarter darker in OKLCH lightness, about as dark as alacritty's own
+/// dim colors, which are the normal colors at two thirds of their RGB values
+fn dim(color: RGBA) -> RGBA {
+    color.darken(color.to_oklch().l / 4.0)
+}
+
+/// Trilinear interpolation between the eight `corners`, indexed like the
+/// ANSI colors: red is bit 0, green bit 1 and blue bit 2
+fn cube(corners: &[RGBA], r: usize, g: usize, b: usize) -> RGBA {
+    let (r, g, b) = (r as f64 / 5.0, g as f64 / 5.0, b as f64 / 5.0);
+
+    let mix_red = |i: usize| corners[i].mix(&corners[i + 1], r);
+    let low = mix_red(0).mix(&mix_red(2), g);
+    let high = mix_red(4).mix(&mix_red(6), g);
+
+    low.mix(&high, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apps::alacritty::Alacritty;
+    use crate::apps::x::X;
+    use crate::apps::TerminalBackend;
+    use crate::palette::Palette;
+
+    #[test]
+    fn it_derives_missing_colors() {
+        let mut colors = Palette::default();
+        for (i, color) in ["#000000", "#cc0000", "#00cc00", "#cccc00"]
+            .iter()
+            .chain(["#0000cc", "#cc00cc", "#00cccc", "#cccccc"].iter())
+            .enumerate()
+        {
+            colors.insert(&format!("color{}", i), color.parse().unwrap());
+        }
+        colors.insert("color9", RGBA(1, 2, 3, 1.0));
+        colors.insert("background", RGBA(10, 10, 10, 1.0));
+
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+        let derived = derive(&theme);
+        let get = |name: &str| *derived.colors.get(name).unwrap();
+
+        assert_eq!(get("color9"), RGBA(1, 2, 3, 1.0));
+        assert!(!derived.colors.derived.contains("color9"));
+        assert!(get("color12").to_oklch().l > get("color4").to_oklch().l);
+        assert!(get("dim_color4").to_oklch().l < get("color4").to_oklch().l);
+
+        // The cube corners are the theme's colors
+        assert_eq!(get("color16"), RGBA(10, 10, 10, 1.0));
+        assert_eq!(get("color196"), get("color1"));
+        assert_eq!(get("color231"), get("color7"));
+        assert_eq!(derived.colors.len(), 16 + 8 + 240 + 1);
+        assert_eq!(derived.colors.derived.len(), 7 + 8 + 240);
+
+        // Apps without a 256-color palette only get the bright colors
+        let restricted = restrict(&derived, |name| X.uses_derived(name));
+        assert_eq!(restricted.colors.len(), 16 + 1);
+        assert_eq!(restricted.colors.derived.len(), 7);
+        assert_eq!(
+            restrict(&derived, |name| Alacritty.uses_derived(name))
+                .colors
+                .len(),
+            16 + 8 + 1
+        );
+    }
+}
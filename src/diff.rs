@@ -0,0 +1,259 @@
+use std::fmt::Write;
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+/// Line based diff of `old` and `new`, computed from their longest common
+/// subsequence. Config files are small so the quadratic table is fine, and the
+/// common prefix and suffix (usually most of the file) are skipped anyway.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the LCS of old_mid[i..] and new_mid[j..]
+    let mut lcs = vec![vec![0usize; new_mid.len() + 1]; old_mid.len() + 1];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result: Vec<DiffLine> = old[..prefix]
+        .iter()
+        .map(|l| DiffLine::Context(l.to_string()))
+        .collect();
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() && j < new_mid.len() {
+        if old_mid[i] == new_mid[j] {
+            result.push(DiffLine::Context(old_mid[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(DiffLine::Removed(old_mid[i].to_string()));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(new_mid[j].to_string()));
+            j += 1;
+        }
+    }
+    result.extend(
+        old_mid[i..]
+            .iter()
+            .map(|l| DiffLine::Removed(l.to_string())),
+    );
+    result.extend(new_mid[j..].iter().map(|l| DiffLine::Added(l.to_string())));
+    result.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|l| DiffLine::Context(l.to_string())),
+    );
+
+    result
+}
+
+/// Groups the output of `diff_lines` into hunks with the usual three lines of
+/// context around each change.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let lines = diff_lines(old, new);
+
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, DiffLine::Context(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    // Merge changes whose context would overlap into (start, end) ranges
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + CONTEXT_LINES + 1).min(lines.len());
+
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            // Line numbers are 1-based, count the lines before the hunk
+            let (old_before, new_before) =
+                lines[..start]
+                    .iter()
+                    .fold((0, 0), |(o, n), line| match line {
+                        DiffLine::Context(_) => (o + 1, n + 1),
+                        DiffLine::Removed(_) => (o + 1, n),
+                        DiffLine::Added(_) => (o, n + 1),
+                    });
+
+            let hunk_lines = lines[start..end].to_vec();
+            let old_len = hunk_lines
+                .iter()
+                .filter(|l| !matches!(l, DiffLine::Added(_)))
+                .count();
+            let new_len = hunk_lines
+                .iter()
+                .filter(|l| !matches!(l, DiffLine::Removed(_)))
+                .count();
+
+            Hunk {
+                old_start: if old_len == 0 {
+                    old_before
+                } else {
+                    old_before + 1
+                },
+                old_len,
+                new_start: if new_len == 0 {
+                    new_before
+                } else {
+                    new_before + 1
+                },
+                new_len,
+                lines: hunk_lines,
+            }
+        })
+        .collect()
+}
+
+/// Renders hunks in unified diff format. With `color` the output contains
+/// ANSI escape codes, the way `git diff` prints to a terminal.
+pub fn unified(old_path: &str, new_path: &str, hunks: &[Hunk], color: bool) -> String {
+    let paint = |code: &str, s: &str| {
+        if color {
+            format!("\u{1b}[{}m{}\u{1b}[0m", code, s)
+        } else {
+            s.to_owned()
+        }
+    };
+
+    let mut output = String::new();
+
+    if hunks.is_empty() {
+        return output;
+    }
+
+    let _ = writeln!(output, "{}", paint("1", &format!("--- {}", old_path)));
+    let _ = writeln!(output, "{}", paint("1", &format!("+++ {}", new_path)));
+
+    for hunk in hunks {
+        let _ = writeln!(output, "{}", paint("36", &hunk.header()));
+
+        for line in &hunk.lines {
+            let _ = match line {
+                DiffLine::Context(l) => writeln!(output, " {}", l),
+                DiffLine::Removed(l) => writeln!(output, "{}", paint("31", &format!("-{}", l))),
+                DiffLine::Added(l) => writeln!(output, "{}", paint("32", &format!("+{}", l))),
+            };
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_diffs_lines() {
+        let old = "a\nb\nc";
+        let new = "a\nB\nc\nd";
+
+        let expected = vec![
+            DiffLine::Context(String::from("a")),
+            DiffLine::Removed(String::from("b")),
+            DiffLine::Added(String::from("B")),
+            DiffLine::Context(String::from("c")),
+            DiffLine::Added(String::from("d")),
+        ];
+
+        assert_eq!(diff_lines(old, new), expected);
+    }
+
+    #[test]
+    fn it_has_no_hunks_for_equal_input() {
+        assert_eq!(hunks("a\nb", "a\nb"), vec![]);
+    }
+
+    #[test]
+    fn it_renders_unified_diff() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12";
+        let new = "1\n2\n3\n4\n5\nsix\n7\n8\n9\n10\n11\n12";
+
+        let expected = "--- foo.conf
++++ foo.conf
+@@ -3,7 +3,7 @@
+ 3
+ 4
+ 5
+-6
++six
+ 7
+ 8
+ 9
+";
+
+        assert_eq!(
+            unified("foo.conf", "foo.conf", &hunks(old, new), false),
+            expected
+        );
+    }
+
+    #[test]
+    fn it_merges_close_changes_into_one_hunk() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8";
+        let new = "one\n2\n3\n4\n5\n6\n7\neight";
+
+        let result = hunks(old, new);
+
+        assert_eq!(result.len(), 1);
+        assert_eq!((result[0].old_start, result[0].old_len), (1, 8));
+    }
+}
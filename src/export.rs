@@ -0,0 +1,122 @@
+use crate::apps::alacritty::Alacritty;
+use crate::apps::kitty::Kitty;
+use crate::apps::termite::Termite;
+use crate::apps::x::X;
+use crate::apps::xterm::XTerm;
+use crate::apps::TerminalBackend;
+use crate::derivation;
+use crate::import::{vscode, windows_terminal};
+use crate::palette::Palette;
+use crate::{ColorName, Theme};
+use failure::{err_msg, Error};
+
+pub const FORMATS: [&str; 7] = [
+    "kitty",
+    "alacritty",
+    "xresources",
+    "xterm",
+    "termite",
+    "windows-terminal",
+    "vscode",
+];
+
+/// A theme converted to the config format of another program, or to a
+/// fresh color section for one of the apps teems manages
+#[derive(Debug, PartialEq)]
+pub struct Export {
+    pub text: String,
+    /// Theme colors the format has no key for. Derived colors are left out,
+    /// they are only exported where they fit.
+    pub skipped: Vec<ColorName>,
+}
+
+pub fn export(theme: &Theme, format: &str) -> Result<Export, Error> {
+    let backend: &dyn TerminalBackend = match format {
+        "kitty" => &Kitty,
+        "alacritty" => &Alacritty,
+        "xresources" => &X,
+        "xterm" => &XTerm,
+        "termite" => &Termite,
+        _ => return export_json(theme, format),
+    };
+
+    let theme = &derivation::restrict(theme, |name| backend.uses_derived(name));
+    let conversion = backend.render(theme)?;
+
+    Ok(Export {
+        text: format!("{}\n", conversion.config.trim()),
+        skipped: theme
+            .colors
+            .names()
+            .into_iter()
+            .filter(|name| !conversion.used.contains(Palette::canonical_name(name)))
+            .filter(|name| !theme.colors.derived.contains(name))
+            .collect(),
+    })
+}
+
+/// The formats of programs that teems doesn't manage, but can share
+/// themes with
+fn export_json(theme: &Theme, format: &str) -> Result<Export, Error> {
+    let (value, keys) = match format {
+        "windows-terminal" => (windows_terminal::export(theme), &windows_terminal::KEYS[..]),
+        "vscode" => (vscode::export(theme), &vscode::KEYS[..]),
+        _ => return Err(err_msg(format!("Can't export to {}", format))),
+    };
+
+    Ok(Export {
+        text: format!("{}\n", serde_json::to_string_pretty(&value)?),
+        skipped: skipped(theme, keys),
+    })
+}
+
+/// The theme's own colors that are not in `keys`
+fn skipped(theme: &Theme, keys: &[(&str, &str)]) -> Vec<ColorName> {
+    theme
+        .colors
+        .names()
+        .into_iter()
+        .filter(|name| !keys.iter().any(|(_, color)| color == name))
+        .filter(|name| !theme.colors.derived.contains(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RGBA;
+
+    #[test]
+    fn it_exports_a_fresh_config() {
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors: vec![
+                (String::from("color1"), RGBA(255, 0, 0, 1.0)),
+                (String::from("background"), RGBA(0, 0, 0, 1.0)),
+                (String::from("url"), RGBA(0, 0, 255, 1.0)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        assert_eq!(
+            export(&theme, "kitty").unwrap(),
+            Export {
+                text: String::from("color1 #ff0000\nbackground #000000\nurl_color #0000ff\n"),
+                skipped: vec![],
+            }
+        );
+        assert_eq!(
+            export(&theme, "xresources").unwrap(),
+            Export {
+                text: String::from("*.color1: #ff0000\n*.background: #000000\n"),
+                skipped: vec![String::from("url")],
+            }
+        );
+        assert_eq!(
+            export(&theme, "alacritty").unwrap().text,
+            "colors:\n  primary:\n    background: '0x000000'\n  normal:\n    red: '0xff0000'\n"
+        );
+    }
+}
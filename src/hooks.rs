@@ -0,0 +1,152 @@
+use failure::{err_msg, Error};
+use std::env;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Runs `command` with `sh -c`. The hook gets the theme name in
+/// `TEEMS_THEME` and the changed files, one per line, in `TEEMS_FILES`.
+pub fn run(command: &str, theme: &str, files: &[PathBuf]) -> Result<(), Error> {
+    let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("TEEMS_THEME", theme)
+        .env("TEEMS_FILES", files.join("\n"))
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(err_msg(format!(
+            "Hook '{}' failed with {}",
+            command, status
+        )))
+    }
+}
+
+/// Loads `paths` into the X resource database, which is what X programs
+/// read their resources from when they start. Without a running X server
+/// there is nothing to merge into.
+pub fn xrdb_merge(paths: &[PathBuf]) -> Result<(), Error> {
+    if env::var_os("DISPLAY").is_none() {
+        return Ok(());
+    }
+
+    for path in paths {
+        let status = Command::new("xrdb").arg("-merge").arg(path).status()?;
+
+        if !status.success() {
+            return Err(err_msg(format!(
+                "xrdb -merge {} failed with {}",
+                path.display(),
+                status
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Process ids of the current user's processes called `name`, from a procfs
+/// mounted at `proc_dir`
+#[cfg(unix)]
+pub fn find_processes(proc_dir: &Path, name: &str) -> Result<Vec<i32>, Error> {
+    let uid = unsafe { libc::getuid() };
+
+    let mut pids: Vec<i32> = fs::read_dir(proc_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_string_lossy().parse::<i32>().ok()?;
+            let owner = entry.metadata().ok()?.uid();
+            let comm = fs::read_to_string(entry.path().join("comm")).ok()?;
+
+            if owner == uid && comm.trim_end() == name {
+                Some(pid)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    pids.sort_unstable();
+
+    Ok(pids)
+}
+
+/// Process ids of the current user's processes called `name`
+#[cfg(target_os = "linux")]
+pub fn user_processes(name: &str) -> Result<Vec<i32>, Error> {
+    find_processes(Path::new("/proc"), name)
+}
+
+// Finding processes needs procfs, which only Linux mounts by default
+#[cfg(not(target_os = "linux"))]
+pub fn user_processes(name: &str) -> Result<Vec<i32>, Error> {
+    Err(err_msg(format!(
+        "Finding running {} processes is only supported on Linux",
+        name
+    )))
+}
+
+/// Sends `signal` to every process of the current user called `name`
+#[cfg(unix)]
+pub fn signal_processes(name: &str, signal: i32) -> Result<(), Error> {
+    for pid in user_processes(name)? {
+        // The process may have exited in the meantime, which is fine
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn it_passes_theme_and_files_to_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+
+        let command = format!(
+            "printf '%s\\n%s' \"$TEEMS_THEME\" \"$TEEMS_FILES\" > {}",
+            out.display()
+        );
+        let files = vec![
+            PathBuf::from("/a/kitty.conf"),
+            PathBuf::from("/b/.Xresources"),
+        ];
+
+        run(&command, "foo", &files).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "foo\n/a/kitty.conf\n/b/.Xresources"
+        );
+        assert!(run("exit 1", "foo", &files).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_finds_processes_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (pid, comm) in &[("12", "termite\n"), ("7", "termite\n"), ("30", "kitty\n")] {
+            fs::create_dir(dir.path().join(pid)).unwrap();
+            fs::write(dir.path().join(pid).join("comm"), comm).unwrap();
+        }
+        fs::create_dir(dir.path().join("self")).unwrap();
+
+        assert_eq!(find_processes(dir.path(), "termite").unwrap(), vec![7, 12]);
+    }
+}
@@ -0,0 +1,129 @@
+pub mod base16;
+pub mod iterm2;
+pub mod vscode;
+pub mod windows_terminal;
+
+use crate::config;
+use crate::{ColorName, RGBA};
+use failure::{err_msg, Error};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::str;
+
+/// A theme read from the color scheme format of another program
+#[derive(Debug, PartialEq)]
+pub struct ImportedTheme {
+    pub name: String,
+    pub colors: Vec<(ColorName, RGBA)>,
+}
+
+impl ImportedTheme {
+    /// The theme as an entry for the theme file
+    pub fn to_value(&self) -> Result<Value, Error> {
+        let mut theme = json!({ "name": self.name, "colors": {} });
+        config::set_colors(&mut theme, &self.colors)?;
+
+        Ok(theme)
+    }
+}
+
+/// Reads the scheme file at `path` in `format`, e.g., `base16`. Formats
+/// without a theme name use the file name.
+pub fn read(format: &str, path: &Path) -> Result<Vec<ImportedTheme>, Error> {
+    let contents = fs::read(path)?;
+    let name = slug(&path.file_stem().unwrap_or_default().to_string_lossy());
+
+    match format {
+        "base16" | "base24" => Ok(vec![base16::parse(str::from_utf8(&contents)?)?]),
+        "iterm2" => Ok(vec![iterm2::parse(&name, &contents)?]),
+        "windows-terminal" => windows_terminal::parse(&json(&contents)?),
+        "vscode" => Ok(vec![vscode::parse(&name, &json(&contents)?)?]),
+        _ => Err(err_msg(format!("Can't import {} schemes", format))),
+    }
+}
+
+/// Parses JSON with the comments and trailing commas that VS Code and
+/// Windows Terminal allow in their settings
+fn json(contents: &[u8]) -> Result<Value, Error> {
+    let mut result = String::with_capacity(contents.len());
+    let mut chars = str::from_utf8(contents)?.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('"', _) => {
+                result.push(c);
+
+                while let Some(c) = chars.next() {
+                    result.push(c);
+
+                    match c {
+                        '\\' => result.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        result.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ('}', _) | (']', _) => {
+                let end = result.trim_end().len();
+                if result[..end].ends_with(',') {
+                    result.remove(end - 1);
+                }
+
+                result.push(c);
+            }
+            _ => result.push(c),
+        }
+    }
+
+    Ok(serde_json::from_str(&result)?)
+}
+
+/// A theme name from the human readable name of a scheme, e.g.,
+/// `tomorrow-night` from "Tomorrow Night"
+pub fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_json_with_comments_and_trailing_commas() {
+        let settings = r#"{
+            // Comment with "quotes"
+            "a": "http://example.com", /* block
+            comment */
+            "b": ["/* not a comment */", "\"",],
+        }"#;
+
+        assert_eq!(
+            json(settings.as_bytes()).unwrap(),
+            json!({ "a": "http://example.com", "b": ["/* not a comment */", "\""] })
+        );
+    }
+}
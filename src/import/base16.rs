@@ -0,0 +1,169 @@
+use crate::import::{slug, ImportedTheme};
+use crate::{literal, ColorName, RGBA};
+use failure::{err_msg, Error};
+use serde_yaml::{Mapping, Value};
+
+/// Theme colors and the base16 color each one comes from, as in
+/// base16-shell. `color16` to `color21` are the base16 colors that have no
+/// ANSI slot, for 256 color terminals.
+const BASE16: [(&str, &str); 27] = [
+    ("color0", "base00"),
+    ("color1", "base08"),
+    ("color2", "base0B"),
+    ("color3", "base0A"),
+    ("color4", "base0D"),
+    ("color5", "base0E"),
+    ("color6", "base0C"),
+    ("color7", "base05"),
+    ("color8", "base03"),
+    ("color9", "base08"),
+    ("color10", "base0B"),
+    ("color11", "base0A"),
+    ("color12", "base0D"),
+    ("color13", "base0E"),
+    ("color14", "base0C"),
+    ("color15", "base07"),
+    ("color16", "base09"),
+    ("color17", "base0F"),
+    ("color18", "base01"),
+    ("color19", "base02"),
+    ("color20", "base04"),
+    ("color21", "base06"),
+    ("foreground", "base05"),
+    ("background", "base00"),
+    ("cursor", "base05"),
+    ("cursor_text", "base00"),
+    ("selection_background", "base02"),
+];
+
+/// What base24 schemes use for the bright colors instead
+const BASE24_BRIGHT: [(&str, &str); 7] = [
+    ("color8", "base02"),
+    ("color9", "base12"),
+    ("color10", "base14"),
+    ("color11", "base13"),
+    ("color12", "base16"),
+    ("color13", "base17"),
+    ("color14", "base15"),
+];
+
+/// Reads a base16 or base24 scheme, either in the original format with the
+/// `baseXX` keys at the top level or in the newer one with a `palette`
+pub fn parse(contents: &str) -> Result<ImportedTheme, Error> {
+    let scheme: Mapping = serde_yaml::from_str(contents)?;
+
+    let text = |key: &str| {
+        scheme
+            .get(&Value::from(key))
+            .and_then(Value::as_str)
+            .map(String::from)
+    };
+
+    let name = text("scheme")
+        .or_else(|| text("name"))
+        .ok_or_else(|| err_msg("The scheme has no name"))?;
+
+    let palette = match scheme.get(&Value::from("palette")) {
+        Some(Value::Mapping(palette)) => palette,
+        Some(_) => return Err(err_msg("'palette' should be a map of colors")),
+        None => &scheme,
+    };
+
+    let base = |key: &str| -> Result<RGBA, Error> {
+        // Older schemes write "base0b", newer ones "base0B"
+        let value = palette
+            .get(&Value::from(key))
+            .or_else(|| palette.get(&Value::from(key.to_lowercase())))
+            .and_then(Value::as_str)
+            .ok_or_else(|| err_msg(format!("The scheme has no color {}", key)))?;
+
+        let value = if value.starts_with('#') {
+            value.to_owned()
+        } else {
+            format!("#{}", value)
+        };
+
+        literal::parse_color(&value).map_err(|e| err_msg(format!("{}: {}", key, e)))
+    };
+
+    let is_base24 =
+        text("system").as_deref() == Some("base24") || palette.contains_key(&Value::from("base12"));
+
+    let mut colors: Vec<(ColorName, RGBA)> = Vec::new();
+
+    for (name, key) in BASE16.iter() {
+        let key = match BASE24_BRIGHT.iter().find(|(n, _)| n == name) {
+            Some((_, base24)) if is_base24 => base24,
+            _ => key,
+        };
+
+        colors.push((name.to_string(), base(key)?));
+    }
+
+    Ok(ImportedTheme {
+        name: slug(&name),
+        colors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_base16_colors() {
+        let scheme = r##"
+scheme: "Tomorrow Night"
+author: "Chris Kempson (http://chriskempson.com)"
+base00: "1d1f21"
+base01: "282a2e"
+base02: "373b41"
+base03: "969896"
+base04: "b4b7b4"
+base05: "c5c8c6"
+base06: "e0e0e0"
+base07: "ffffff"
+base08: "cc6666"
+base09: "de935f"
+base0A: "f0c674"
+base0B: "b5bd68"
+base0C: "8abeb7"
+base0D: "81a2be"
+base0E: "b294bb"
+base0F: "a3685a"
+"##;
+
+        let theme = parse(scheme).unwrap();
+        let get = |name: &str| theme.colors.iter().find(|(n, _)| n == name).unwrap().1;
+
+        assert_eq!(theme.name, "tomorrow-night");
+        assert_eq!(get("color0"), RGBA(0x1d, 0x1f, 0x21, 1.0));
+        assert_eq!(get("color2"), RGBA(0xb5, 0xbd, 0x68, 1.0));
+        assert_eq!(get("color8"), RGBA(0x96, 0x98, 0x96, 1.0));
+        assert_eq!(get("color16"), RGBA(0xde, 0x93, 0x5f, 1.0));
+        assert_eq!(get("selection_background"), RGBA(0x37, 0x3b, 0x41, 1.0));
+    }
+
+    #[test]
+    fn it_reads_base24_palettes() {
+        let mut scheme = String::from("system: \"base24\"\nname: \"Some Theme\"\npalette:\n");
+        for i in 0..24 {
+            scheme.push_str(&format!("  base{:02X}: \"#0000{:02x}\"\n", i, i));
+        }
+
+        let theme = parse(&scheme).unwrap();
+        let get = |name: &str| theme.colors.iter().find(|(n, _)| n == name).unwrap().1;
+
+        assert_eq!(theme.name, "some-theme");
+        assert_eq!(get("color1"), RGBA(0, 0, 0x08, 1.0));
+        assert_eq!(get("color9"), RGBA(0, 0, 0x12, 1.0));
+        assert_eq!(get("color12"), RGBA(0, 0, 0x16, 1.0));
+
+        assert_eq!(
+            parse("scheme: x\nbase00: \"000000\"")
+                .unwrap_err()
+                .to_string(),
+            "The scheme has no color base08"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::color::{DisplayP3, LinearRgb};
+use crate::import::{slug, ImportedTheme};
+use crate::{ColorName, RGBA};
+use failure::{err_msg, Error};
+use plist::{Dictionary, Value};
+
+/// The keys of an .itermcolors file and the theme colors they become
+const KEYS: [(&str, &str); 8] = [
+    ("Foreground Color", "foreground"),
+    ("Background Color", "background"),
+    ("Cursor Color", "cursor"),
+    ("Cursor Text Color", "cursor_text"),
+    ("Selection Color", "selection_background"),
+    ("Selected Text Color", "selection_foreground"),
+    ("Link Color", "url"),
+    ("Bold Color", "foreground_bold"),
+];
+
+/// Reads an iTerm2 .itermcolors property list. These have no name inside,
+/// so the theme gets `name`, usually the file name.
+pub fn parse(name: &str, contents: &[u8]) -> Result<ImportedTheme, Error> {
+    let colors = Value::from_reader(std::io::Cursor::new(contents))?
+        .into_dictionary()
+        .ok_or_else(|| err_msg("Expected a dictionary of colors"))?;
+
+    let ansi = (0..16).map(|i| (format!("Ansi {} Color", i), format!("color{}", i)));
+    let named = KEYS
+        .iter()
+        .map(|(key, color)| (key.to_string(), color.to_string()));
+
+    let mut result: Vec<(ColorName, RGBA)> = Vec::new();
+
+    for (key, color) in ansi.chain(named) {
+        if let Some(value) = colors.get(&key) {
+            let components = value
+                .as_dictionary()
+                .ok_or_else(|| err_msg(format!("{}: expected a dictionary", key)))?;
+
+            result.push((
+                color,
+                parse_color(components).map_err(|e| err_msg(format!("{}: {}", key, e)))?,
+            ));
+        }
+    }
+
+    if result.is_empty() {
+        return Err(err_msg("The file has no colors iTerm2 uses"));
+    }
+
+    Ok(ImportedTheme {
+        name: slug(name),
+        colors: result,
+    })
+}
+
+/// A color from its `Red Component`, ... keys, in the color space of its
+/// `Color Space` key
+fn parse_color(components: &Dictionary) -> Result<RGBA, String> {
+    let component = |name: &str| -> Result<f64, String> {
+        let key = format!("{} Component", name);
+
+        match components.get(&key) {
+            Some(Value::Real(v)) => Ok(*v),
+            Some(Value::Integer(v)) => v
+                .as_signed()
+                .map(|v| v as f64)
+                .ok_or_else(|| format!("{} is too large", key)),
+            Some(_) => Err(format!("{} is not a number", key)),
+            None => Err(format!("{} is missing", key)),
+        }
+        .map(|v| v.clamp(0.0, 1.0))
+    };
+
+    let (r, g, b) = (component("Red")?, component("Green")?, component("Blue")?);
+    let alpha = component("Alpha").unwrap_or(1.0) as f32;
+
+    let space = components
+        .get("Color Space")
+        .and_then(Value::as_string)
+        .unwrap_or("sRGB");
+
+    let channel = |v: f64| (v * 255.0).round() as u8;
+
+    match space {
+        "sRGB" | "Device" => Ok(RGBA(channel(r), channel(g), channel(b), alpha)),
+        "P3" => Ok(RGBA::from_display_p3(DisplayP3 { r, g, b }, alpha)),
+        // Generic RGB, which is close to sRGB but has a gamma of 1.8
+        "Calibrated" => Ok(RGBA::from_linear(
+            LinearRgb {
+                r: r.powf(1.8),
+                g: g.powf(1.8),
+                b: b.powf(1.8),
+            },
+            alpha,
+        )),
+        _ => Err(format!("unknown color space '{}'", space)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, space: Option<&str>, rgb: (f64, f64, f64)) -> String {
+        format!(
+            "<key>{}</key><dict>{}<key>Red Component</key><real>{}</real>\
+             <key>Green Component</key><real>{}</real>\
+             <key>Blue Component</key><real>{}</real></dict>",
+            key,
+            space.map_or(String::new(), |s| format!(
+                "<key>Color Space</key><string>{}</string>",
+                s
+            )),
+            rgb.0,
+            rgb.1,
+            rgb.2
+        )
+    }
+
+    fn plist(entries: &[String]) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0"><dict>{}</dict></plist>"#,
+            entries.concat()
+        )
+    }
+
+    #[test]
+    fn it_reads_colors_in_every_color_space() {
+        let file = plist(&[
+            entry("Ansi 1 Color", Some("sRGB"), (0.8, 0.4, 0.4)),
+            entry("Ansi 15 Color", None, (1.0, 1.0, 1.0)),
+            entry("Background Color", Some("P3"), (1.0, 0.0, 0.0)),
+            entry("Selected Text Color", Some("Calibrated"), (0.5, 0.5, 0.5)),
+            entry("Cursor Guide Color", Some("sRGB"), (0.0, 0.0, 0.0)),
+        ]);
+
+        let theme = parse("Solarized Dark", file.as_bytes()).unwrap();
+
+        assert_eq!(theme.name, "solarized-dark");
+        assert_eq!(
+            theme.colors,
+            vec![
+                (String::from("color1"), RGBA(204, 102, 102, 1.0)),
+                (String::from("color15"), RGBA(255, 255, 255, 1.0)),
+                // P3 red is outside of sRGB
+                (String::from("background"), RGBA(255, 0, 0, 1.0)),
+                (
+                    String::from("selection_foreground"),
+                    RGBA(146, 146, 146, 1.0)
+                ),
+            ]
+        );
+
+        let lab = plist(&[entry("Ansi 0 Color", Some("Lab"), (0.0, 0.0, 0.0))]);
+        assert_eq!(
+            parse("x", lab.as_bytes()).unwrap_err().to_string(),
+            "Ansi 0 Color: unknown color space 'Lab'"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::import::ImportedTheme;
+use crate::{literal, Theme};
+use failure::{err_msg, Error};
+use serde_json::{json, Map, Value};
+
+/// The settings key VS Code keeps color overrides under
+const CUSTOMIZATIONS: &str = "workbench.colorCustomizations";
+
+/// The keys of VS Code's integrated terminal colors and the theme colors for
+/// them
+pub const KEYS: [(&str, &str); 22] = [
+    ("terminal.background", "background"),
+    ("terminal.foreground", "foreground"),
+    ("terminalCursor.foreground", "cursor"),
+    ("terminalCursor.background", "cursor_text"),
+    ("terminal.selectionBackground", "selection_background"),
+    ("terminal.selectionForeground", "selection_foreground"),
+    ("terminal.ansiBlack", "color0"),
+    ("terminal.ansiRed", "color1"),
+    ("terminal.ansiGreen", "color2"),
+    ("terminal.ansiYellow", "color3"),
+    ("terminal.ansiBlue", "color4"),
+    ("terminal.ansiMagenta", "color5"),
+    ("terminal.ansiCyan", "color6"),
+    ("terminal.ansiWhite", "color7"),
+    ("terminal.ansiBrightBlack", "color8"),
+    ("terminal.ansiBrightRed", "color9"),
+    ("terminal.ansiBrightGreen", "color10"),
+    ("terminal.ansiBrightYellow", "color11"),
+    ("terminal.ansiBrightBlue", "color12"),
+    ("terminal.ansiBrightMagenta", "color13"),
+    ("terminal.ansiBrightCyan", "color14"),
+    ("terminal.ansiBrightWhite", "color15"),
+];
+
+/// Reads the terminal colors from a VS Code settings.json, or from just its
+/// `workbench.colorCustomizations` object. The settings have no theme name,
+/// so the theme gets `name`.
+pub fn parse(name: &str, json: &Value) -> Result<ImportedTheme, Error> {
+    let colors = json
+        .get(CUSTOMIZATIONS)
+        .unwrap_or(json)
+        .as_object()
+        .ok_or_else(|| err_msg(format!("Expected an object with {}", CUSTOMIZATIONS)))?;
+
+    let mut result = Vec::new();
+
+    for (key, color) in KEYS.iter() {
+        if let Some(value) = colors.get(*key) {
+            let value = value
+                .as_str()
+                .ok_or_else(|| err_msg(format!("{}: expected a string", key)))?;
+            let value =
+                literal::parse_color(value).map_err(|e| err_msg(format!("{}: {}", key, e)))?;
+
+            result.push((color.to_string(), value));
+        }
+    }
+
+    if result.is_empty() {
+        return Err(err_msg("The settings have no terminal colors"));
+    }
+
+    Ok(ImportedTheme {
+        name: name.to_owned(),
+        colors: result,
+    })
+}
+
+/// The theme as VS Code settings, to paste into settings.json
+pub fn export(theme: &Theme) -> Value {
+    let mut colors = Map::new();
+
+    for (key, color) in KEYS.iter() {
+        if let Some(value) = theme.colors.get(color) {
+            colors.insert(key.to_string(), Value::from(value.to_css_hex()));
+        }
+    }
+
+    json!({ CUSTOMIZATIONS: colors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RGBA;
+
+    #[test]
+    fn it_converts_in_both_directions() {
+        let settings = json!({
+            "editor.fontSize": 14,
+            "workbench.colorCustomizations": {
+                "editor.background": "#000000",
+                "terminal.ansiBrightMagenta": "#c678dd",
+                "terminal.selectionBackground": "#ffffff40"
+            }
+        });
+
+        let theme = parse("one", &settings).unwrap();
+
+        assert_eq!(
+            theme.colors,
+            vec![
+                (
+                    String::from("selection_background"),
+                    RGBA(255, 255, 255, 0.2509804)
+                ),
+                (String::from("color13"), RGBA(198, 120, 221, 1.0)),
+            ]
+        );
+
+        let theme: Theme = serde_json::from_value(theme.to_value().unwrap()).unwrap();
+        assert_eq!(
+            export(&theme),
+            json!({
+                "workbench.colorCustomizations": {
+                    "terminal.selectionBackground": "#ffffff40",
+                    "terminal.ansiBrightMagenta": "#c678dd"
+                }
+            })
+        );
+    }
+}
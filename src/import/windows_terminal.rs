@@ -0,0 +1,118 @@
+use crate::import::{slug, ImportedTheme};
+use crate::{literal, Theme};
+use failure::{err_msg, Error};
+use serde_json::{Map, Value};
+
+/// The keys of a Windows Terminal color scheme and the theme colors for them
+pub const KEYS: [(&str, &str); 20] = [
+    ("background", "background"),
+    ("foreground", "foreground"),
+    ("cursorColor", "cursor"),
+    ("selectionBackground", "selection_background"),
+    ("black", "color0"),
+    ("red", "color1"),
+    ("green", "color2"),
+    ("yellow", "color3"),
+    ("blue", "color4"),
+    ("purple", "color5"),
+    ("cyan", "color6"),
+    ("white", "color7"),
+    ("brightBlack", "color8"),
+    ("brightRed", "color9"),
+    ("brightGreen", "color10"),
+    ("brightYellow", "color11"),
+    ("brightBlue", "color12"),
+    ("brightPurple", "color13"),
+    ("brightCyan", "color14"),
+    ("brightWhite", "color15"),
+];
+
+/// Reads Windows Terminal color schemes: a single scheme, a list of them,
+/// or a settings.json with a `schemes` list
+pub fn parse(json: &Value) -> Result<Vec<ImportedTheme>, Error> {
+    let schemes = match json.get("schemes").unwrap_or(json) {
+        Value::Array(schemes) => schemes.iter().collect(),
+        scheme @ Value::Object(_) => vec![scheme],
+        _ => return Err(err_msg("Expected a color scheme or a list of them")),
+    };
+
+    schemes.into_iter().map(parse_scheme).collect()
+}
+
+fn parse_scheme(scheme: &Value) -> Result<ImportedTheme, Error> {
+    let name = scheme
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| err_msg("A scheme has no name"))?;
+
+    let mut colors = Vec::new();
+
+    for (key, color) in KEYS.iter() {
+        if let Some(value) = scheme.get(key) {
+            let value = value
+                .as_str()
+                .ok_or_else(|| err_msg(format!("Scheme '{}', {}: expected a string", name, key)))?;
+            let value = literal::parse_color(value)
+                .map_err(|e| err_msg(format!("Scheme '{}', {}: {}", name, key, e)))?;
+
+            colors.push((color.to_string(), value));
+        }
+    }
+
+    Ok(ImportedTheme {
+        name: slug(name),
+        colors,
+    })
+}
+
+/// The theme as a Windows Terminal scheme, for the `schemes` list of its
+/// settings.json. Windows Terminal has no alpha, so colors are opaque.
+pub fn export(theme: &Theme) -> Value {
+    let mut scheme = Map::new();
+    scheme.insert(String::from("name"), Value::from(theme.name.as_str()));
+
+    for (key, color) in KEYS.iter() {
+        if let Some(value) = theme.colors.get(color) {
+            scheme.insert(key.to_string(), Value::from(value.to_hex()));
+        }
+    }
+
+    Value::Object(scheme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RGBA;
+    use serde_json::json;
+
+    #[test]
+    fn it_converts_in_both_directions() {
+        let settings = json!({
+            "profiles": {},
+            "schemes": [
+                { "name": "Campbell", "background": "#0C0C0C", "purple": "#881798", "cursorColor": "#FFFFFF" },
+                { "name": "One Half Dark", "brightPurple": "#C678DD" }
+            ]
+        });
+
+        let themes = parse(&settings).unwrap();
+
+        assert_eq!(themes[0].name, "campbell");
+        assert_eq!(
+            themes[0].colors,
+            vec![
+                (String::from("background"), RGBA(12, 12, 12, 1.0)),
+                (String::from("cursor"), RGBA(255, 255, 255, 1.0)),
+                (String::from("color5"), RGBA(136, 23, 152, 1.0)),
+            ]
+        );
+        assert_eq!(themes[1].name, "one-half-dark");
+
+        let theme: Theme = serde_json::from_value(themes[0].to_value().unwrap()).unwrap();
+        assert_eq!(
+            export(&theme),
+            json!({ "name": "campbell", "background": "#0c0c0c", "cursorColor": "#ffffff", "purple": "#881798" })
+        );
+    }
+}
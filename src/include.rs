@@ -0,0 +1,87 @@
+use crate::apps::{Conversion, TerminalBackend};
+use crate::{FileChange, Theme};
+use failure::{err_msg, Error};
+use std::fs;
+use std::path::Path;
+
+/// Renders `theme` into the backend's own colors file next to the config at
+/// `path` and makes sure `config` includes that file. Returns the conversion
+/// of the main config and the change to the colors file.
+pub fn apply(
+    backend: &dyn TerminalBackend,
+    theme: &Theme,
+    path: &Path,
+    config: &str,
+) -> Result<(Conversion, FileChange), Error> {
+    let include_path = backend.include_path(path).ok_or_else(|| {
+        err_msg(format!(
+            "{} can't include other files, use a different strategy",
+            backend.name()
+        ))
+    })?;
+
+    let rendered = backend.render(theme)?;
+
+    let created = !include_path.exists();
+    let old = if created {
+        String::new()
+    } else {
+        fs::read_to_string(&include_path)?
+    };
+
+    let include = FileChange {
+        app: backend.name().to_owned(),
+        path: include_path.clone(),
+        old,
+        new: format!(
+            "{} Generated by teems, changes will be overwritten\n{}\n",
+            backend.comment(),
+            rendered.config.trim_end_matches('\n')
+        ),
+        missing: rendered.missing,
+        used: rendered.used,
+        created,
+    };
+
+    let main = Conversion {
+        config: backend.add_include(config, &include_path)?,
+        ..Conversion::default()
+    };
+
+    Ok((main, include))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apps::kitty::Kitty;
+    use crate::RGBA;
+
+    #[test]
+    fn it_renders_colors_file_and_includes_it_once() {
+        let colors = vec![(String::from("color1"), RGBA(1, 1, 1, 1.0))]
+            .into_iter()
+            .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kitty.conf");
+
+        let (main, include) = apply(&Kitty, &theme, &path, "font_size 12\n").unwrap();
+
+        assert_eq!(main.config, "font_size 12\ninclude teems-colors.conf\n");
+        assert_eq!(include.path, dir.path().join("teems-colors.conf"));
+        assert!(include.created);
+        assert_eq!(
+            include.new,
+            "# Generated by teems, changes will be overwritten\ncolor1 #010101\n"
+        );
+
+        let (again, _) = apply(&Kitty, &theme, &path, &main.config).unwrap();
+        assert_eq!(again.config, main.config);
+    }
+}
@@ -1,30 +1,70 @@
 pub mod apps;
-
-use failure::Error;
-use failure_derive::Fail;
-use serde::de::{self, Deserialize, Deserializer};
+pub mod backup;
+pub mod block;
+pub mod color;
+pub mod config;
+pub mod contrast;
+pub mod derivation;
+pub mod diff;
+pub mod export;
+pub mod hooks;
+pub mod import;
+pub mod include;
+pub mod literal;
+pub mod palette;
+pub mod reference;
+pub mod sequences;
+pub mod settings;
+pub mod transaction;
+
+pub use crate::apps::{Conversion, MissingColor, Registry, TerminalBackend};
+use crate::backup::BackupStore;
+use crate::import::ImportedTheme;
+use crate::literal::RawColor;
+use crate::palette::Palette;
+use crate::settings::Strategy;
+use failure::{err_msg, Error, Fail};
+use serde::de::{self, Deserializer};
+use serde::ser::{Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
-use std::num::ParseIntError;
 use std::path::PathBuf;
+use std::str::FromStr;
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
 
-// TODO: Error on color not found in theme
-
 type ColorName = String;
 
 type ThemeName = String;
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct RGBA(u8, u8, u8, f32);
 
 impl RGBA {
-    fn to_hex(&self) -> String {
-        format!("#{:0>2x}{:0>2x}{:0>2x}", &self.0, &self.1, &self.2,)
+    fn to_hex(self) -> String {
+        format!("#{:0>2x}{:0>2x}{:0>2x}", self.0, self.1, self.2,)
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` if the color is not opaque
+    pub fn to_css_hex(self) -> String {
+        if self.3 < 1.0 {
+            format!("{}{:0>2x}", self.to_hex(), (self.3 * 255.0).round() as u8)
+        } else {
+            self.to_hex()
+        }
+    }
+}
+
+impl Serialize for RGBA {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_css_hex())
     }
 }
 
@@ -33,43 +73,92 @@ impl<'d> de::Deserialize<'d> for RGBA {
     where
         D: Deserializer<'d>,
     {
-        #[derive(Deserialize)]
-        #[serde(untagged)]
-        enum RGBAHelper {
-            Str(String),
-            Array(u8, u8, u8, f32),
-        }
-
-        match Deserialize::deserialize(deserializer)? {
-            RGBAHelper::Str(str) => {
-                if str.len() != 7 {
-                    Err(de::Error::custom(
-                        "Hex color string must be of format #ABCDEF",
-                    ))
-                } else {
-                    // This only works on ASCII
-                    let rgb = &str[1..]
-                        .as_bytes()
-                        .chunks_exact(2)
-                        .map(|c| {
-                            let s = c.iter().map(|&byte| byte as char).collect::<String>();
-                            u8::from_str_radix(&s, 16)
-                        })
-                        .collect::<Result<Vec<u8>, ParseIntError>>()
-                        .map_err(de::Error::custom)?;
-
-                    Ok(RGBA(rgb[0], rgb[1], rgb[2], 1.0))
-                }
+        RawColor::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for RGBA {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RGBA, String> {
+        literal::parse_color(s)
+    }
+}
+
+/// A theme as it is written in the theme file
+#[derive(Deserialize)]
+struct RawTheme {
+    name: ThemeName,
+    #[serde(default)]
+    extends: Option<ThemeName>,
+    #[serde(default)]
+    colors: HashMap<ColorName, RawColor>,
+}
+
+impl RawTheme {
+    /// The theme followed by its parent, the parent's parent and so on
+    fn ancestry<'a>(
+        &'a self,
+        themes: &HashMap<&str, &'a RawTheme>,
+    ) -> Result<Vec<&'a RawTheme>, Error> {
+        let mut chain = vec![self];
+        let mut current = self;
+
+        while let Some(parent_name) = &current.extends {
+            let parent = themes.get(parent_name.as_str()).ok_or_else(|| {
+                err_msg(format!(
+                    "Theme '{}' extends '{}', which doesn't exist",
+                    current.name, parent_name
+                ))
+            })?;
+
+            if let Some(start) = chain.iter().position(|t| t.name == parent.name) {
+                let cycle: Vec<&str> = chain[start..]
+                    .iter()
+                    .chain(std::iter::once(parent))
+                    .map(|t| t.name.as_str())
+                    .collect();
+
+                return Err(err_msg(format!(
+                    "Themes extend each other in a cycle: {}",
+                    cycle.join(" -> ")
+                )));
             }
-            RGBAHelper::Array(r, g, b, alpha) => Ok(RGBA(r, g, b, alpha)),
+
+            chain.push(parent);
+            current = parent;
         }
+
+        Ok(chain)
+    }
+}
+
+impl TryFrom<RawTheme> for Theme {
+    type Error = String;
+
+    fn try_from(raw: RawTheme) -> Result<Theme, String> {
+        let name = raw.name;
+        let colors =
+            Palette::from_raw(raw.colors).map_err(|e| format!("Theme '{}', {}", name, e))?;
+
+        Ok(Theme {
+            name,
+            extends: raw.extends,
+            colors,
+        })
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(try_from = "RawTheme")]
 pub struct Theme {
     pub name: ThemeName,
-    pub colors: HashMap<ColorName, RGBA>,
+    /// The theme this one takes its colors from before applying its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<ThemeName>,
+    pub colors: Palette,
 }
 
 impl fmt::Display for Theme {
@@ -80,43 +169,111 @@ impl fmt::Display for Theme {
         output.push_str("Colors:\n");
 
         // TODO: Implement display for RGBA
-        for (color, value) in &self.colors {
+        for (color, value) in self.colors.iter() {
             output.push_str(&format!("\t: {:?}: {:?}", color, value));
         }
 
-        output.push_str("\n");
-        output.push_str("\n");
+        output.push('\n');
+        output.push('\n');
 
         write!(f, "{}", output)
     }
 }
 
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum AppError {
-    #[fail(display = "Error during color conversion: {}", msg)]
     ConversionError { msg: String },
+    MissingColors { report: ColorReport },
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::ConversionError { msg } => {
+                write!(f, "Error during color conversion: {}", msg)
+            }
+            AppError::MissingColors { report } => write!(
+                f,
+                "Theme is missing {} color(s) used in the configs, nothing was written\n{}",
+                report.missing_count(),
+                report.to_string().trim_end()
+            ),
+        }
+    }
 }
 
+impl Fail for AppError {}
+
 type Config = Vec<Theme>;
 
-pub struct App {
-    pub config_paths: Vec<PathBuf>,
-    pub name: String,
-    pub mk_config: Box<Fn(&Theme, &str) -> Result<String, Error>>,
+/// The themes that ship with teems, usable without a theme file
+const BUILTIN_THEMES: &str = include_str!("../themes/builtin.json");
+
+/// Parses the contents of a theme file. A theme that `extends` another gets
+/// all colors of its parent (and of the parent's parent and so on), and
+/// overrides the ones it sets itself.
+pub fn load_themes(contents: &str) -> Result<Config, Error> {
+    resolve_themes(serde_json::from_str(contents)?)
 }
 
-impl App {
-    pub fn new(
-        name: String,
-        config_paths: Vec<PathBuf>,
-        mk_config: Box<Fn(&Theme, &str) -> Result<String, Error>>,
-    ) -> App {
-        App {
-            name,
-            config_paths,
-            mk_config,
+pub fn builtin_themes() -> Config {
+    load_themes(BUILTIN_THEMES).expect("The built-in themes are invalid")
+}
+
+/// The themes of a theme file followed by the built-in ones. A theme in the
+/// file replaces the built-in theme of the same name, and can extend any
+/// built-in theme.
+pub fn load_themes_with_builtins(contents: Option<&str>) -> Result<Config, Error> {
+    let mut raw: Vec<RawTheme> = match contents {
+        Some(contents) => serde_json::from_str(contents)?,
+        None => vec![],
+    };
+    let builtin: Vec<RawTheme> = serde_json::from_str(BUILTIN_THEMES)?;
+
+    for theme in builtin {
+        if !raw.iter().any(|t| t.name == theme.name) {
+            raw.push(theme);
         }
     }
+
+    resolve_themes(raw)
+}
+
+fn resolve_themes(raw: Vec<RawTheme>) -> Result<Config, Error> {
+    let mut by_name: HashMap<&str, &RawTheme> = HashMap::new();
+    for theme in &raw {
+        by_name.entry(&theme.name).or_insert(theme);
+    }
+
+    raw.iter()
+        .map(|theme| {
+            let mut colors: HashMap<ColorName, RawColor> = HashMap::new();
+
+            for ancestor in theme.ancestry(&by_name)?.into_iter().rev() {
+                // An override can use another name for the same color, like
+                // `text` for `cursor_text`
+                colors.retain(|name, _| {
+                    !ancestor
+                        .colors
+                        .keys()
+                        .any(|own| Palette::canonical_name(own) == Palette::canonical_name(name))
+                });
+                colors.extend(
+                    ancestor
+                        .colors
+                        .iter()
+                        .map(|(name, color)| (name.clone(), color.clone())),
+                );
+            }
+
+            Theme::try_from(RawTheme {
+                name: theme.name.clone(),
+                extends: theme.extends.clone(),
+                colors,
+            })
+            .map_err(err_msg)
+        })
+        .collect()
 }
 
 pub fn list_themes(config: Config) {
@@ -125,36 +282,313 @@ pub fn list_themes(config: Config) {
     }
 }
 
-pub fn activate_theme(apps: Vec<App>, theme: &Theme) -> Result<(), Error> {
-    let home_dir = dirs::home_dir().unwrap();
-    // config_dir is Library/Preferences on MacOS but I don't think anyone
-    // really stores configuration for e.g., terminal emulators there.
-    let config_dir_os = dirs::config_dir().unwrap();
-    let config_dir_linux = home_dir.join(".config");
+/// The theme names with every theme indented below the one it extends
+pub fn theme_tree(config: &[Theme]) -> String {
+    fn add_children(config: &[Theme], parent: Option<&str>, depth: usize, output: &mut String) {
+        for theme in config.iter().filter(|t| t.extends.as_deref() == parent) {
+            output.push_str(&format!("{}{}\n", "  ".repeat(depth), theme.name));
+            add_children(config, Some(&theme.name), depth + 1, output);
+        }
+    }
+
+    let mut output = String::new();
+    add_children(config, None, 0, &mut output);
+
+    output
+}
+
+/// The result of running a backend's `convert` on one of its config files,
+/// before anything is written to disk.
+#[derive(Debug, Default, PartialEq)]
+pub struct FileChange {
+    pub app: String,
+    pub path: PathBuf,
+    pub old: String,
+    pub new: String,
+    pub missing: Vec<MissingColor>,
+    pub used: BTreeSet<ColorName>,
+    /// The file doesn't exist yet and is created by this change
+    pub created: bool,
+}
+
+impl FileChange {
+    pub fn is_modified(&self) -> bool {
+        self.created || self.old != self.new
+    }
+
+    pub fn hunks(&self) -> Vec<diff::Hunk> {
+        diff::hunks(&self.old, &self.new)
+    }
+
+    pub fn unified_diff(&self, color: bool) -> String {
+        let path = self.path.to_string_lossy();
+        let old_path = if self.created { "/dev/null" } else { &path };
+
+        diff::unified(old_path, &path, &self.hunks(), color)
+    }
+}
+
+/// A theme named `name` with the colors the configs of the app `app` set
+/// now. With the include strategy, the colors file is read after the config
+/// that includes it.
+pub fn capture_theme(registry: &Registry, app: &str, name: &str) -> Result<ImportedTheme, Error> {
+    let backend = registry
+        .get(app)
+        .ok_or_else(|| err_msg(format!("Unknown app {}", app)))?;
+
+    let mut paths = vec![];
+    for path in registry.config_paths(backend) {
+        let include = backend.include_path(&path).filter(|p| p.exists());
+
+        paths.push(path);
+        paths.extend(include);
+    }
+
+    let mut colors: Vec<(ColorName, RGBA)> = vec![];
+    for path in &paths {
+        let config = fs::read_to_string(path)?;
+        let captured = backend
+            .capture(&config)
+            .map_err(|e| err_msg(format!("{}: {}", path.display(), e)))?;
+
+        for (color, value) in captured {
+            colors.retain(|(c, _)| *c != color);
+            colors.push((color, value));
+        }
+    }
+
+    if colors.is_empty() {
+        return Err(err_msg(format!("Found no colors in the {} configs", app)));
+    }
+
+    colors.sort_by(|(a, _), (b, _)| apps::compare_color_names(a, b));
+
+    Ok(ImportedTheme {
+        name: name.to_owned(),
+        colors,
+    })
+}
+
+/// Runs every backend's `convert` in memory and returns the resulting
+/// changes without touching any file. Backends that share a config file (x
+/// and xterm both use .Xresources) each get the output of the one before.
+pub fn plan_activation(
+    registry: &Registry,
+    theme: &Theme,
+    options: &ActivateOptions,
+) -> Result<Vec<FileChange>, Error> {
+    let mut changes: Vec<FileChange> = vec![];
+
+    for backend in registry.enabled() {
+        let theme = &derivation::restrict(theme, |name| backend.uses_derived(name));
+        let insert_missing = options.insert_missing
+            || registry
+                .settings()
+                .app(backend.name())
+                .and_then(|a| a.insert_missing)
+                .unwrap_or(false);
+
+        for path in registry.config_paths(backend) {
+            let config = match changes.iter().rev().find(|c| c.path == path) {
+                Some(previous) => previous.new.clone(),
+                None => fs::read_to_string(&path)?,
+            };
+
+            let mut conversion = match registry.settings().strategy(backend.name()) {
+                Strategy::Rewrite => {
+                    let mut conversion = backend.convert(theme, &config)?;
+
+                    if insert_missing {
+                        backend.insert_missing(theme, &mut conversion)?;
+                    }
+
+                    conversion
+                }
+                Strategy::Block => block::apply(backend, theme, &config)?,
+                Strategy::Include => {
+                    let (conversion, include) = include::apply(backend, theme, &path, &config)?;
+                    changes.push(include);
+                    conversion
+                }
+            };
+
+            // The converters work line by line and drop the final newline
+            if config.ends_with('\n') && !conversion.config.ends_with('\n') {
+                conversion.config.push('\n');
+            }
+
+            changes.push(FileChange {
+                app: backend.name().to_owned(),
+                path,
+                old: config,
+                new: conversion.config,
+                missing: conversion.missing,
+                used: conversion.used,
+                created: false,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Theme colors that could not be applied, either because a config has a key
+/// the theme has no color for, or because no config has a key for a color.
+#[derive(Debug, Default, PartialEq)]
+pub struct ColorReport {
+    /// App name, config file and the keys in it that kept their old value
+    pub missing: Vec<(String, PathBuf, Vec<MissingColor>)>,
+    /// Theme colors that no app consumed
+    pub unused: Vec<ColorName>,
+}
 
-    for app in apps {
-        let mut valid_paths: Vec<PathBuf> = app
-            .config_paths
+impl ColorReport {
+    pub fn new(theme: &Theme, changes: &[FileChange]) -> ColorReport {
+        let missing = changes
             .iter()
-            .flat_map(|p| vec![config_dir_linux.join(p), config_dir_os.join(p)].into_iter())
-            .filter(|p| p.exists())
+            .filter(|c| !c.missing.is_empty())
+            .map(|c| (c.app.clone(), c.path.clone(), c.missing.clone()))
+            .collect();
+
+        let used: BTreeSet<&ColorName> = changes.iter().flat_map(|c| c.used.iter()).collect();
+
+        let mut unused: Vec<ColorName> = theme
+            .colors
+            .names()
+            .into_iter()
+            .filter(|k| !used.contains(k) && !theme.colors.derived.contains(k))
             .collect();
+        apps::sort_color_names(&mut unused);
+
+        ColorReport { missing, unused }
+    }
+
+    pub fn missing_count(&self) -> usize {
+        self.missing.iter().map(|(_, _, m)| m.len()).sum()
+    }
+}
+
+impl fmt::Display for ColorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (app, path, missing) in &self.missing {
+            let keys: Vec<String> = missing
+                .iter()
+                .map(|m| {
+                    if m.key == m.color {
+                        m.key.clone()
+                    } else {
+                        format!("{} ({})", m.key, m.color)
+                    }
+                })
+                .collect();
+
+            writeln!(
+                f,
+                "{}: kept old value for {} in {}",
+                app,
+                keys.join(", "),
+                path.display()
+            )?;
+        }
+
+        if !self.unused.is_empty() {
+            writeln!(f, "Not used by any app: {}", self.unused.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ActivateOptions {
+    /// Fail instead of keeping old values for colors the theme doesn't have
+    pub strict: bool,
+    /// Add entries for theme colors that a config has no key for. Can also
+    /// be enabled per app in the settings.
+    pub insert_missing: bool,
+    /// Also recolor the user's running terminals with escape sequences
+    pub live: bool,
+}
+
+/// The result of `activate_theme`
+#[derive(Debug)]
+pub struct Activated {
+    pub changes: Vec<FileChange>,
+    /// The apps whose configs were written, each once
+    pub apps: Vec<String>,
+}
+
+/// Writes the new configs for `theme`. All configs are generated before the
+/// first one is written and the writes are all-or-nothing. Every file that is
+/// about to be modified is copied to `backups` first, so the activation can
+/// be undone.
+pub fn activate_theme(
+    registry: &Registry,
+    theme: &Theme,
+    backups: &BackupStore,
+    options: &ActivateOptions,
+) -> Result<Activated, Error> {
+    let changes = plan_activation(registry, theme, options)?;
+
+    if options.strict {
+        let report = ColorReport::new(theme, &changes);
+
+        if report.missing_count() > 0 {
+            return Err(AppError::MissingColors { report }.into());
+        }
+    }
 
-        valid_paths.sort();
-        valid_paths.dedup();
+    // For files changed by more than one backend, the first change has the
+    // contents that are on disk now
+    let mut paths: Vec<PathBuf> = vec![];
+    for change in changes.iter().filter(|c| c.is_modified()) {
+        if !paths.contains(&change.path) {
+            paths.push(change.path.clone());
+        }
+    }
+    let activation = backups.snapshot(&theme.name, &paths)?;
+
+    // The files were rolled back, so there is nothing to undo
+    if let Err(e) = transaction::commit(&changes) {
+        backups.discard(&activation)?;
+        return Err(e);
+    }
 
-        for path in valid_paths {
-            let config = fs::read_to_string(&path)?;
+    let mut apps: Vec<String> = vec![];
+    for change in changes.iter().filter(|c| c.is_modified()) {
+        if !apps.contains(&change.app) {
+            apps.push(change.app.clone());
+        }
+    }
 
-            let new_config = (app.mk_config)(&theme, &config)?;
+    for backend in registry.enabled() {
+        let changed = changes
+            .iter()
+            .any(|c| c.app == backend.name() && c.is_modified());
+
+        if changed && registry.settings().reloads(backend.name()) {
+            let theme = derivation::restrict(theme, |name| backend.uses_derived(name));
+
+            if let Err(e) = backend.reload(&theme, &registry.config_paths(backend)) {
+                eprintln!("Could not reload {}: {}", backend.name(), e);
+            }
+        }
+    }
 
-            fs::write(&path, new_config)?;
+    for hook in &registry.settings().hooks {
+        if let Err(e) = hooks::run(hook, &theme.name, &paths) {
+            eprintln!("{}", e);
+        }
+    }
 
-            println!("{} \u{2713}", app.name);
+    if options.live {
+        match sequences::apply_live(theme) {
+            Ok(terminals) => println!("Recolored {} terminal(s)", terminals.len()),
+            Err(e) => eprintln!("Could not recolor running terminals: {}", e),
         }
     }
 
-    Ok(())
+    Ok(Activated { changes, apps })
 }
 
 #[cfg(test)]
@@ -171,13 +605,14 @@ mod tests {
           }
         }"##;
 
-        let res: Theme = serde_json::from_str(&s).unwrap();
-        let mut colors = HashMap::new();
+        let res: Theme = serde_json::from_str(s).unwrap();
+        let mut colors = Palette::default();
 
-        colors.insert(String::from("color1"), RGBA(255, 170, 187, 1.0));
+        colors.insert("color1", RGBA(255, 170, 187, 1.0));
 
         let expect = Theme {
             name: String::from("foo"),
+            extends: None,
             colors,
         };
 
@@ -194,16 +629,230 @@ mod tests {
           }
         }"##;
 
-        let res: Theme = serde_json::from_str(&s).unwrap();
-        let mut colors = HashMap::new();
+        let res: Theme = serde_json::from_str(s).unwrap();
+        let mut colors = Palette::default();
 
-        colors.insert(String::from("color1"), RGBA(255, 170, 187, 1.0));
+        colors.insert("color1", RGBA(255, 170, 187, 1.0));
 
         let expect = Theme {
             name: String::from("foo"),
+            extends: None,
             colors,
         };
 
         assert_eq!(res, expect);
     }
+
+    #[test]
+    fn it_names_theme_and_key_in_errors() {
+        let s = r##"{ "name": "foo", "colors": { "color1": "#ff00ff", "color2": "#ggg" } }"##;
+
+        let error = serde_json::from_str::<Theme>(s).unwrap_err().to_string();
+
+        assert!(error.starts_with("Theme 'foo', color 'color2': Invalid color '#ggg'"));
+    }
+
+    #[test]
+    fn it_resolves_extends() {
+        let s = r##"[
+          { "name": "base", "colors": { "color1": "#ff0000", "background": "#000000", "text": "#111111" } },
+          { "name": "light", "extends": "base", "colors": { "background": "#ffffff" } },
+          { "name": "light-hc", "extends": "light", "colors": { "cursor_text": "#222222" } }
+        ]"##;
+
+        let themes = load_themes(s).unwrap();
+        let theme = &themes[2];
+
+        assert_eq!(theme.colors.get("color1"), Some(&RGBA(255, 0, 0, 1.0)));
+        assert_eq!(
+            theme.colors.get("background"),
+            Some(&RGBA(255, 255, 255, 1.0))
+        );
+        assert_eq!(
+            theme.colors.get("cursor_text"),
+            Some(&RGBA(34, 34, 34, 1.0))
+        );
+        assert!(theme.colors.warnings.is_empty());
+        assert_eq!(
+            themes[0].colors.get("background"),
+            Some(&RGBA(0, 0, 0, 1.0))
+        );
+
+        assert_eq!(theme_tree(&themes), "base\n  light\n    light-hc\n");
+    }
+
+    #[test]
+    fn it_rejects_missing_parents_and_cycles() {
+        let missing = r##"[{ "name": "a", "extends": "nord", "colors": {} }]"##;
+        assert_eq!(
+            load_themes(missing).unwrap_err().to_string(),
+            "Theme 'a' extends 'nord', which doesn't exist"
+        );
+
+        let cycle = r##"[
+          { "name": "c", "extends": "a" },
+          { "name": "a", "extends": "b" },
+          { "name": "b", "extends": "a" }
+        ]"##;
+        assert_eq!(
+            load_themes(cycle).unwrap_err().to_string(),
+            "Themes extend each other in a cycle: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn it_merges_builtin_themes() {
+        for theme in builtin_themes() {
+            assert!(theme.colors.warnings.is_empty(), "{}", theme.name);
+            assert_eq!(theme.colors.ansi.iter().flatten().count(), 16);
+        }
+
+        let s = r##"[
+          { "name": "nord", "colors": { "background": "#000000" } },
+          { "name": "dracula-dark", "extends": "dracula", "colors": { "background": "#000000" } }
+        ]"##;
+
+        let themes = load_themes_with_builtins(Some(s)).unwrap();
+        let get = |name: &str| themes.iter().find(|t| t.name == name).unwrap();
+
+        assert_eq!(themes.len(), builtin_themes().len() + 1);
+        assert_eq!(get("nord").colors.len(), 1);
+        assert_eq!(
+            get("dracula-dark").colors.get("color1"),
+            Some(&RGBA(255, 85, 85, 1.0))
+        );
+        assert_eq!(load_themes_with_builtins(None).unwrap(), builtin_themes());
+    }
+
+    #[test]
+    fn it_reports_missing_and_unused_colors() {
+        let colors = vec![
+            (String::from("color1"), RGBA(1, 1, 1, 1.0)),
+            (String::from("url"), RGBA(2, 2, 2, 1.0)),
+            (String::from("color10"), RGBA(3, 3, 3, 1.0)),
+            (String::from("color9"), RGBA(4, 4, 4, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+
+        let conversion =
+            apps::kitty::convert_colors(&theme, "color1 #000000\ncolor2 #000000").unwrap();
+        let change = FileChange {
+            app: String::from("kitty"),
+            path: PathBuf::from("kitty.conf"),
+            old: String::new(),
+            new: conversion.config,
+            missing: conversion.missing,
+            used: conversion.used,
+            created: false,
+        };
+
+        let report = ColorReport::new(&theme, &[change]);
+
+        assert_eq!(report.missing_count(), 1);
+        assert_eq!(report.missing[0].2[0].key, "color2");
+        assert_eq!(report.unused, vec!["color9", "color10", "url"]);
+    }
+
+    #[test]
+    fn it_chains_backends_that_share_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".Xresources");
+        fs::write(&path, "*.color0: #ffffff\nXTerm*color0: #ffffff\n").unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(Box::new(apps::x::X));
+        registry.register(Box::new(apps::xterm::XTerm));
+        registry.set_settings(
+            settings::Settings::parse(&format!(
+                "[apps.x]\npaths = [{:?}]\n[apps.xterm]\npaths = [{:?}]",
+                path, path
+            ))
+            .unwrap(),
+        );
+
+        let colors = vec![(String::from("color0"), RGBA(0, 0, 0, 1.0))]
+            .into_iter()
+            .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+
+        let changes = plan_activation(&registry, &theme, &ActivateOptions::default()).unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].old, changes[0].new);
+        assert_eq!(changes[1].new, "*.color0: #000000\nXTerm*color0: #000000\n");
+    }
+
+    #[test]
+    fn it_keeps_no_history_for_failed_activations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kitty.conf");
+        fs::write(&path, "color0 #ffffff\n").unwrap();
+        // Writing the temporary file fails if a directory is in its place
+        fs::create_dir(dir.path().join(".kitty.conf.teems-tmp")).unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(Box::new(apps::kitty::Kitty));
+        registry.set_settings(
+            settings::Settings::parse(&format!("[apps.kitty]\npaths = [{:?}]", path)).unwrap(),
+        );
+
+        let colors = vec![(String::from("color0"), RGBA(0, 0, 0, 1.0))]
+            .into_iter()
+            .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+        let backups = BackupStore::new(dir.path().join("backups"));
+
+        assert!(activate_theme(&registry, &theme, &backups, &ActivateOptions::default()).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "color0 #ffffff\n");
+        assert!(backups.history().unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_lists_only_the_apps_that_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let x_path = dir.path().join(".Xresources");
+        let xterm_path = dir.path().join(".Xdefaults");
+        fs::write(&x_path, "*.color0: #000000\n").unwrap();
+        fs::write(&xterm_path, "XTerm*color0: #ffffff\n").unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(Box::new(apps::x::X));
+        registry.register(Box::new(apps::xterm::XTerm));
+        registry.set_settings(
+            settings::Settings::parse(&format!(
+                "[apps.x]\npaths = [{:?}]\nreload = false\n[apps.xterm]\npaths = [{:?}]\nreload = false",
+                x_path, xterm_path
+            ))
+            .unwrap(),
+        );
+
+        let colors = vec![(String::from("color0"), RGBA(0, 0, 0, 1.0))]
+            .into_iter()
+            .collect();
+        let theme = Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        };
+        let backups = BackupStore::new(dir.path().join("backups"));
+
+        let activated =
+            activate_theme(&registry, &theme, &backups, &ActivateOptions::default()).unwrap();
+
+        assert_eq!(activated.apps, vec![String::from("xterm")]);
+    }
 }
@@ -0,0 +1,382 @@
+use crate::color::Hsl;
+use crate::RGBA;
+use serde::de::IgnoredAny;
+use serde_derive::Deserialize;
+
+/// A color as it is written in a theme file, before it is parsed
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RawColor {
+    Str(String),
+    Array(Vec<f32>),
+    Other(IgnoredAny),
+}
+
+impl RawColor {
+    pub fn parse(&self) -> Result<RGBA, String> {
+        match self {
+            RawColor::Str(s) => parse_color(s),
+            RawColor::Array(values) => match values[..] {
+                [r, g, b] => Ok(RGBA(channel(r)?, channel(g)?, channel(b)?, 1.0)),
+                [r, g, b, a] => Ok(RGBA(channel(r)?, channel(g)?, channel(b)?, alpha(a)?)),
+                _ => Err(format!(
+                    "expected [r, g, b] or [r, g, b, alpha], got {} values",
+                    values.len()
+                )),
+            },
+            RawColor::Other(_) => Err(String::from(
+                "expected a color string like \"#ff0000\" or an array like [255, 0, 0, 1.0]",
+            )),
+        }
+    }
+}
+
+fn channel(value: f32) -> Result<u8, String> {
+    if (0.0..=255.0).contains(&value) {
+        Ok(value.round() as u8)
+    } else {
+        Err(format!("{} is not between 0 and 255", value))
+    }
+}
+
+fn alpha(value: f32) -> Result<f32, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("alpha {} is not between 0 and 1", value))
+    }
+}
+
+/// Parses a color literal: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`,
+/// `0xrrggbb`, `rgb()`/`rgba()`, `hsl()`/`hsla()` or a CSS color name
+pub fn parse_color(literal: &str) -> Result<RGBA, String> {
+    let s = literal.trim().to_ascii_lowercase();
+
+    let result = if let Some(hex) = s.strip_prefix('#') {
+        parse_hex(hex)
+    } else if let Some(hex) = s.strip_prefix("0x") {
+        match hex.len() {
+            6 => parse_hex(hex),
+            _ => Err(String::from("expected 0xrrggbb")),
+        }
+    } else if let Some(open) = s.find('(') {
+        match (&s[..open], s[open + 1..].strip_suffix(')')) {
+            (_, None) => Err(String::from("missing ')'")),
+            ("rgb", Some(args)) | ("rgba", Some(args)) => parse_rgb(args),
+            ("hsl", Some(args)) | ("hsla", Some(args)) => parse_hsl(args),
+            (function, _) => Err(format!("unknown color function '{}'", function)),
+        }
+    } else {
+        named_color(&s).ok_or_else(|| String::from("unknown color name"))
+    };
+
+    result.map_err(|e| format!("Invalid color '{}': {}", literal, e))
+}
+
+fn parse_hex(hex: &str) -> Result<RGBA, String> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(String::from("not a hex number"));
+    }
+
+    let digits: Vec<u8> = hex
+        .chars()
+        .filter_map(|c| c.to_digit(16))
+        .map(|d| d as u8)
+        .collect();
+
+    let bytes: Vec<u8> = match digits.len() {
+        // #rgb and #rgba repeat each digit, #f80 is #ff8800
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|c| c[0] * 16 + c[1]).collect(),
+        _ => return Err(String::from("expected 3, 4, 6 or 8 hex digits")),
+    };
+
+    let a = bytes.get(3).map_or(1.0, |a| f32::from(*a) / 255.0);
+
+    Ok(RGBA(bytes[0], bytes[1], bytes[2], a))
+}
+
+/// Splits the arguments of a color function. Accepts both `1, 2, 3, 0.5`
+/// and the newer `1 2 3 / 0.5`.
+fn arguments(args: &str) -> Vec<&str> {
+    args.split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// A number, or a percentage of `max`
+fn number(arg: &str, max: f32) -> Result<f32, String> {
+    let (value, scale) = match arg.strip_suffix('%') {
+        Some(percent) => (percent, max / 100.0),
+        None => (arg, 1.0),
+    };
+
+    value
+        .parse::<f32>()
+        .map(|v| v * scale)
+        .map_err(|_| format!("'{}' is not a number", arg))
+}
+
+fn parse_alpha(args: &[&str]) -> Result<f32, String> {
+    match args.get(3) {
+        Some(a) => alpha(number(a, 1.0)?),
+        None => Ok(1.0),
+    }
+}
+
+fn parse_rgb(args: &str) -> Result<RGBA, String> {
+    let args = arguments(args);
+
+    if args.len() != 3 && args.len() != 4 {
+        return Err(String::from("expected 3 or 4 arguments"));
+    }
+
+    Ok(RGBA(
+        channel(number(args[0], 255.0)?)?,
+        channel(number(args[1], 255.0)?)?,
+        channel(number(args[2], 255.0)?)?,
+        parse_alpha(&args)?,
+    ))
+}
+
+fn parse_hsl(args: &str) -> Result<RGBA, String> {
+    let args = arguments(args);
+
+    if args.len() != 3 && args.len() != 4 {
+        return Err(String::from("expected 3 or 4 arguments"));
+    }
+
+    let hue = number(args[0].trim_end_matches("deg"), 1.0)?;
+    let saturation = number(args[1].trim_end_matches('%'), 1.0)? / 100.0;
+    let lightness = number(args[2].trim_end_matches('%'), 1.0)? / 100.0;
+
+    if !(0.0..=1.0).contains(&saturation) || !(0.0..=1.0).contains(&lightness) {
+        return Err(String::from(
+            "saturation and lightness must be between 0% and 100%",
+        ));
+    }
+
+    let hsl = Hsl {
+        h: f64::from(hue),
+        s: f64::from(saturation),
+        l: f64::from(lightness),
+    };
+
+    Ok(RGBA::from_hsl(hsl, parse_alpha(&args)?))
+}
+
+fn named_color(name: &str) -> Option<RGBA> {
+    if name == "transparent" {
+        return Some(RGBA(0, 0, 0, 0.0));
+    }
+
+    CSS_COLORS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, rgb)| RGBA((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8, 1.0))
+}
+
+/// The CSS named colors, https://www.w3.org/TR/css-color-4/#named-colors
+const CSS_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_hex_colors() {
+        assert_eq!(parse_color("#f80"), Ok(RGBA(255, 136, 0, 1.0)));
+        assert_eq!(parse_color("#FF8800"), Ok(RGBA(255, 136, 0, 1.0)));
+        assert_eq!(parse_color("#ff880000"), Ok(RGBA(255, 136, 0, 0.0)));
+        assert_eq!(parse_color("0xff8800"), Ok(RGBA(255, 136, 0, 1.0)));
+        assert!(parse_color("#ff88").unwrap().3 > 0.5);
+
+        assert!(parse_color("#ff880").is_err());
+        // Must not panic on multi byte characters
+        assert!(parse_color("#ffäää").is_err());
+    }
+
+    #[test]
+    fn it_parses_color_functions() {
+        assert_eq!(parse_color("rgb(255, 136, 0)"), Ok(RGBA(255, 136, 0, 1.0)));
+        assert_eq!(
+            parse_color("rgba(100%, 0%, 0%, 0.5)"),
+            Ok(RGBA(255, 0, 0, 0.5))
+        );
+        assert_eq!(
+            parse_color("rgb(255 136 0 / 50%)"),
+            Ok(RGBA(255, 136, 0, 0.5))
+        );
+        assert_eq!(parse_color("hsl(0, 100%, 50%)"), Ok(RGBA(255, 0, 0, 1.0)));
+        assert_eq!(
+            parse_color("hsl(120deg 100% 25%)"),
+            Ok(RGBA(0, 128, 0, 1.0))
+        );
+        assert_eq!(
+            parse_color("hsla(240, 100%, 50%, 0.25)"),
+            Ok(RGBA(0, 0, 255, 0.25))
+        );
+
+        assert!(parse_color("rgb(256, 0, 0)").is_err());
+        assert!(parse_color("rgb(0, 0)").is_err());
+        assert!(parse_color("lab(50 0 0)").is_err());
+    }
+
+    #[test]
+    fn it_parses_named_colors() {
+        assert_eq!(parse_color("RebeccaPurple"), Ok(RGBA(102, 51, 153, 1.0)));
+        assert_eq!(
+            parse_color("reddish"),
+            Err(String::from("Invalid color 'reddish': unknown color name"))
+        );
+    }
+}
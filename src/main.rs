@@ -1,71 +1,482 @@
-use clap::{crate_version, App, Arg, SubCommand};
+use clap::{crate_version, App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
 use std::fs;
-use std::path::PathBuf;
-use teems_rust::{activate_theme, apps, list_themes, App as TermEmu, Theme};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use teems_rust::backup::BackupStore;
+use teems_rust::config::{self, ThemeFile};
+use teems_rust::contrast::{self, Thresholds};
+use teems_rust::derivation;
+use teems_rust::export;
+use teems_rust::import::{self, ImportedTheme};
+use teems_rust::sequences;
+use teems_rust::settings::Settings;
+use teems_rust::{
+    activate_theme, builtin_themes, capture_theme, list_themes, load_themes_with_builtins,
+    plan_activation, theme_tree, ActivateOptions, ColorReport, Registry, Theme, RGBA,
+};
 
 fn main() {
-    let alacritty = TermEmu::new(
-        String::from("alacritty"),
-        vec![PathBuf::from(r"alacritty/alacritty.yml")],
-        Box::new(apps::alacritty::convert_colors),
-    );
-
-    let x = TermEmu::new(
-        String::from("x"),
-        vec![PathBuf::from(r".Xresources")],
-        Box::new(apps::x::convert_colors),
-    );
-
-    let xterm = TermEmu::new(
-        String::from("xterm"),
-        vec![PathBuf::from(r".Xresources")],
-        Box::new(apps::xterm::convert_colors),
-    );
-
-    let kitty = TermEmu::new(
-        String::from("kitty"),
-        vec![PathBuf::from(r".Xresources")],
-        Box::new(apps::kitty::convert_colors),
-    );
-
-    let termite = TermEmu::new(
-        String::from("termite"),
-        vec![PathBuf::from(r".Xresources")],
-        Box::new(apps::termite::convert_colors),
-    );
-
-    let apps = vec![alacritty, x, xterm, kitty, termite];
-
     let app = App::new("Teems")
         .version(crate_version!())
         .author("Florian B. <yuuki@protonmail.com")
         .about("Easily switch themes for your terminal(s)")
-        .subcommand(SubCommand::with_name("list").about("List all themes"))
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List all themes")
+                .arg(Arg::from_usage(
+                    "--tree 'indent themes below the theme they extend'",
+                ))
+                .arg(Arg::from_usage(
+                    "--builtin 'only list the themes that come with teems'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("undo").about("Restore the configs of the last activation"),
+        )
+        .subcommand(SubCommand::with_name("history").about("List past activations"))
+        .subcommand(SubCommand::with_name("apps").about("List supported terminals"))
         .subcommand(
             SubCommand::with_name("activate")
                 .about("Activate a theme")
                 .arg(Arg::from_usage(
                     "-t, --theme <THEME> 'a required name of a theme'",
+                ))
+                .arg(Arg::from_usage(
+                    "-n, --dry-run 'print a diff of the changes instead of writing them'",
+                ))
+                .arg(Arg::from_usage(
+                    "--strict 'fail if the theme is missing colors used in the configs'",
+                ))
+                .arg(Arg::from_usage(
+                    "-i, --insert-missing 'add theme colors that a config has no entry for'",
+                ))
+                .arg(Arg::from_usage(
+                    "-l, --live 'also recolor running terminals with escape sequences'",
+                ))
+                .arg(Arg::from_usage(
+                    "-d, --derive 'fill missing bright, dim and 256 colors from the theme'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("sequences")
+                .about("Print the escape sequences that set a theme's colors, e.g. for a shell rc file")
+                .arg(Arg::from_usage("<THEME> 'name of a theme'"))
+                .arg(Arg::from_usage(
+                    "-d, --derive 'fill missing bright colors from the theme'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("fix-contrast")
+                .about("Adjust the lightness of theme colors that don't have enough contrast")
+                .arg(Arg::from_usage("<THEME> 'name of a theme'"))
+                .arg(Arg::from_usage(
+                    "--min-ratio [RATIO] 'WCAG contrast ratio to reach, defaults to 4.5 (AA)'",
+                ))
+                .arg(Arg::from_usage(
+                    "--ignore [COLORS] 'comma separated colors to leave alone, e.g. color0,color8'",
+                ))
+                .arg(
+                    Arg::from_usage("--name [NAME] 'name of the new theme, defaults to <THEME>-contrast'")
+                        .conflicts_with("in-place"),
+                )
+                .arg(Arg::from_usage("--in-place 'change the theme itself'"))
+                .arg(Arg::from_usage(
+                    "-n, --dry-run 'only print the changes, don't write the theme file'",
                 )),
         )
-        .args(&[Arg::from_usage(
-            "-c, --config <FILE> 'a required json file containing the themes'",
-        )]);
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Add color schemes of other programs to the theme file")
+                .arg(Arg::from_usage("<FORMAT> 'format of the scheme files'").possible_values(&["base16", "base24", "iterm2", "windows-terminal", "vscode"]))
+                .arg(Arg::from_usage("<FILES>... 'scheme files'"))
+                .arg(Arg::from_usage(
+                    "--name [NAME] 'theme name, if there is one theme. Defaults to the scheme or file name'",
+                ))
+                .arg(Arg::from_usage(
+                    "-n, --dry-run 'print the themes instead of adding them'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("capture")
+                .about("Save the colors an app's config uses now as a new theme")
+                .arg(Arg::from_usage("--from <APP> 'app to read the colors from, see apps'"))
+                .arg(Arg::from_usage("--name <NAME> 'name of the new theme'"))
+                .arg(Arg::from_usage(
+                    "-n, --dry-run 'print the theme instead of adding it'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print a theme in the color scheme format of another program")
+                .arg(Arg::from_usage("<THEME> 'name of a theme'"))
+                .arg(
+                    Arg::from_usage("-f, --format <FORMAT> 'format to print the theme in'")
+                        .possible_values(&export::FORMATS),
+                )
+                .arg(Arg::from_usage(
+                    "-d, --derive 'fill missing bright, dim and 256 colors from the theme'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check the contrast of theme colors against the background")
+                .arg(Arg::from_usage(
+                    "[THEME] 'name of a theme, checks all themes if omitted'",
+                ))
+                .arg(Arg::from_usage(
+                    "--min-ratio [RATIO] 'minimum WCAG contrast ratio, defaults to 4.5 (AA)'",
+                ))
+                .arg(Arg::from_usage(
+                    "--min-lc [LC] 'minimum absolute APCA Lc, not checked by default'",
+                ))
+                .arg(Arg::from_usage(
+                    "--ignore [COLORS] 'comma separated colors to skip, e.g. color0,color8'",
+                )),
+        )
+        .args(&[
+            Arg::from_usage("-c, --config [FILE] 'a json file containing your themes, added to the built-in ones'"),
+            Arg::from_usage(
+                "-s, --settings [FILE] 'teems settings, defaults to ~/.config/teems/config.toml'",
+            ),
+        ]);
 
     let matches = app.get_matches();
-    // TODO: Add dry run flag
 
-    let config_path = matches
-        .value_of("config")
-        .expect("Couldn't read '--config' value");
+    let settings_path = matches
+        .value_of("settings")
+        .map(PathBuf::from)
+        .unwrap_or_else(Settings::default_path);
+
+    let settings = Settings::load(&settings_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ::std::process::exit(1);
+    });
+
+    let mut registry = Registry::with_defaults();
+    registry.set_settings(settings);
 
-    let config = fs::read_to_string(config_path).expect("Error reading config file");
-    let config = serde_json::from_str(&config);
+    let backups = BackupStore::default_location().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ::std::process::exit(1);
+    });
+
+    match matches.subcommand() {
+        ("apps", _) => {
+            for backend in registry.iter() {
+                let paths = registry.config_paths(backend);
+
+                if !registry.settings().is_enabled(backend.name()) {
+                    println!("{:<12}disabled", backend.name());
+                } else if paths.is_empty() && backend.detect() {
+                    // Only happens if the settings replace the default paths
+                    println!(
+                        "{:<12}not found at the paths in the settings, but in the default location",
+                        backend.name()
+                    );
+                } else if paths.is_empty() {
+                    println!("{:<12}not found", backend.name());
+                } else {
+                    for path in paths {
+                        println!("{:<12}{}", backend.name(), path.display());
+                    }
+                }
+            }
+            return;
+        }
+        ("undo", _) => {
+            match backups.undo() {
+                Ok(Some(activation)) => {
+                    for file in activation.files {
+                        println!("Restored {}", file.path.display());
+                    }
+                    println!("Undid activation of {}", activation.theme);
+                }
+                Ok(None) => println!("Nothing to undo"),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ::std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ("history", _) => {
+            match backups.history() {
+                Ok(history) => {
+                    for activation in history.iter().rev() {
+                        println!("{}  {}", activation.date(), activation.theme);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ::std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let config_path = matches.value_of("config");
+
+    if let ("import", Some(sub)) = matches.subcommand() {
+        import_themes(sub, Path::new(require_config(config_path)));
+        return;
+    }
+
+    if let ("capture", Some(sub)) = matches.subcommand() {
+        let app = sub.value_of("from").unwrap_or_default();
+        let name = sub.value_of("name").unwrap_or_default();
+
+        let result = capture_theme(&registry, app, name).and_then(|theme| {
+            let value = theme.to_value()?;
+
+            if sub.is_present("dry-run") {
+                println!("{}", serde_json::to_string_pretty(&value)?);
+            } else {
+                let config_path = require_config(config_path);
+                let path = Path::new(config_path);
+
+                let mut file = ThemeFile::load(path)?;
+                file.append(value)?;
+                file.save(path)?;
+
+                println!("Added {} to {}", name, config_path);
+            }
+
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            ::std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // A theme file that doesn't exist yet has no themes, commands like
+    // fix-contrast create it
+    let config = match config_path.map(fs::read_to_string) {
+        Some(Ok(contents)) => Some(contents),
+        Some(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => None,
+        Some(Err(e)) => {
+            eprintln!("Could not read {}: {}", config_path.unwrap_or_default(), e);
+            ::std::process::exit(1);
+        }
+        None => None,
+    };
+    let config = load_themes_with_builtins(config.as_deref());
 
     match config {
         Ok(cfg) => match matches.subcommand() {
-            ("list", _) => {
-                list_themes(cfg);
+            ("list", Some(sub)) => {
+                if sub.is_present("builtin") {
+                    let file = match config_path {
+                        Some(path) => ThemeFile::load(Path::new(path)),
+                        None => Ok(ThemeFile { themes: vec![] }),
+                    };
+
+                    for theme in builtin_themes() {
+                        match &file {
+                            Ok(file) if file.position(&theme.name).is_some() => {
+                                println!("{} (replaced by the theme file)", theme.name)
+                            }
+                            _ => println!("{}", theme.name),
+                        }
+                    }
+                } else if sub.is_present("tree") {
+                    print!("{}", theme_tree(&cfg));
+                } else {
+                    list_themes(cfg);
+                }
+            }
+            ("check", Some(sub)) => {
+                let thresholds = thresholds(sub);
+
+                let themes: Vec<Theme> = match sub.value_of("THEME") {
+                    Some(name) => cfg.into_iter().filter(|t: &Theme| t.name == name).collect(),
+                    None => cfg,
+                };
+
+                if themes.is_empty() {
+                    eprintln!(
+                        "Theme {} not found in config file",
+                        sub.value_of("THEME").unwrap_or_default()
+                    );
+                    ::std::process::exit(1);
+                }
+
+                let mut failed = 0;
+
+                for theme in &themes {
+                    match contrast::check(theme) {
+                        Ok(mut report) => {
+                            report.thresholds = thresholds.clone();
+                            println!("{}", report);
+
+                            if !report.failures().is_empty() {
+                                failed += 1;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}\n", e);
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if failed > 0 {
+                    eprintln!(
+                        "{} of {} theme(s) failed the contrast check",
+                        failed,
+                        themes.len()
+                    );
+                    ::std::process::exit(1);
+                }
+            }
+            ("fix-contrast", Some(sub)) => {
+                let theme_name = sub
+                    .value_of("THEME")
+                    .expect("Could not read theme argument");
+
+                let theme = cfg
+                    .into_iter()
+                    .find(|x: &Theme| x.name == theme_name)
+                    .unwrap_or_else(|| {
+                        eprintln!("Theme {} not found in config file", theme_name);
+                        ::std::process::exit(1);
+                    });
+
+                let thresholds = thresholds(sub);
+
+                let (fixes, unfixable) = contrast::fix_contrast(&theme, &thresholds)
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        ::std::process::exit(1);
+                    });
+
+                for fix in &fixes {
+                    println!("{}", fix);
+                }
+
+                for name in &unfixable {
+                    eprintln!("{} can't reach {}:1", name, thresholds.min_ratio);
+                }
+
+                if fixes.is_empty() {
+                    println!("Nothing to fix in {}", theme.name);
+                } else if !sub.is_present("dry-run") {
+                    let default_name = format!("{}-contrast", theme.name);
+                    let new_name = if sub.is_present("in-place") {
+                        theme.name.clone()
+                    } else {
+                        sub.value_of("name").unwrap_or(&default_name).to_owned()
+                    };
+
+                    let colors: Vec<(String, RGBA)> =
+                        fixes.iter().map(|f| (f.name.clone(), f.after)).collect();
+
+                    let config_path = require_config(config_path);
+
+                    let result = (|| -> Result<(), failure::Error> {
+                        let path = Path::new(config_path);
+                        let mut file = ThemeFile::load(path)?;
+
+                        match file.get_mut(&theme.name) {
+                            Some(value) if sub.is_present("in-place") => {
+                                config::set_colors(value, &colors)?;
+                            }
+                            found => {
+                                // A built-in theme gets a copy in the theme
+                                // file, which replaces it when in place
+                                let mut value = match found {
+                                    Some(value) => value.clone(),
+                                    None => json!({ "name": theme.name, "colors": theme.colors }),
+                                };
+                                value["name"] = serde_json::Value::String(new_name.clone());
+                                config::set_colors(&mut value, &colors)?;
+                                file.append(value)?;
+                            }
+                        }
+
+                        file.save(path)
+                    })();
+
+                    match result {
+                        Ok(()) => println!("Wrote {} to {}", new_name, config_path),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            ::std::process::exit(1);
+                        }
+                    }
+                }
+
+                if !unfixable.is_empty() {
+                    ::std::process::exit(1);
+                }
+            }
+            ("export", Some(sub)) => {
+                let theme_name = sub
+                    .value_of("THEME")
+                    .expect("Could not read theme argument");
+
+                let theme = cfg
+                    .into_iter()
+                    .find(|x: &Theme| x.name == theme_name)
+                    .unwrap_or_else(|| {
+                        eprintln!("Theme {} not found in config file", theme_name);
+                        ::std::process::exit(1);
+                    });
+
+                print_warnings(&theme);
+
+                let theme = if sub.is_present("derive") || registry.settings().derive {
+                    derivation::derive(&theme)
+                } else {
+                    theme
+                };
+
+                let format = sub.value_of("format").unwrap_or_default();
+
+                match export::export(&theme, format) {
+                    Ok(export) => {
+                        if !export.skipped.is_empty() {
+                            eprintln!(
+                                "Warning: {} has no place for {}",
+                                format,
+                                export.skipped.join(", ")
+                            );
+                        }
+
+                        print!("{}", export.text);
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        ::std::process::exit(1);
+                    }
+                }
+            }
+            ("sequences", Some(sub)) => {
+                let theme_name = sub
+                    .value_of("THEME")
+                    .expect("Could not read theme argument");
+
+                match cfg.into_iter().find(|x: &Theme| x.name == theme_name) {
+                    Some(theme) => {
+                        print_warnings(&theme);
+
+                        if sub.is_present("derive") || registry.settings().derive {
+                            print!("{}", sequences::sequences(&derivation::derive(&theme)));
+                        } else {
+                            print!("{}", sequences::sequences(&theme));
+                        }
+                    }
+                    None => {
+                        eprintln!("Theme {} not found in config file", theme_name);
+                        ::std::process::exit(1);
+                    }
+                }
             }
             ("activate", Some(sub)) => {
                 let theme_name = sub
@@ -80,11 +491,65 @@ fn main() {
                         ::std::process::exit(0);
                     });
 
-                match activate_theme(apps, &theme) {
-                    Ok(_) => println!("Done!"),
+                print_warnings(&theme);
+
+                let theme = if sub.is_present("derive") || registry.settings().derive {
+                    derivation::derive(&theme)
+                } else {
+                    theme
+                };
+
+                let options = ActivateOptions {
+                    strict: sub.is_present("strict"),
+                    insert_missing: sub.is_present("insert-missing"),
+                    live: sub.is_present("live"),
+                };
+
+                if sub.is_present("dry-run") {
+                    match plan_activation(&registry, &theme, &options) {
+                        Ok(changes) => {
+                            let color = io::stdout().is_terminal();
+
+                            for change in &changes {
+                                if change.is_modified() {
+                                    print!("{}", change.unified_diff(color));
+                                } else {
+                                    println!(
+                                        "{}: no changes to {}",
+                                        change.app,
+                                        change.path.display()
+                                    );
+                                }
+                            }
+
+                            let report = ColorReport::new(&theme, &changes);
+                            eprint!("{}", report);
+
+                            if options.strict && report.missing_count() > 0 {
+                                ::std::process::exit(1);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            ::std::process::exit(1);
+                        }
+                    }
+
+                    return;
+                }
+
+                match activate_theme(&registry, &theme, &backups, &options) {
+                    Ok(activated) => {
+                        for app in &activated.apps {
+                            println!("{} \u{2713}", app);
+                        }
+
+                        eprint!("{}", ColorReport::new(&theme, &activated.changes));
+                        println!("Done!");
+                    }
                     Err(e) => {
                         eprintln!("{}", e);
-                        ::std::process::exit(0);
+                        ::std::process::exit(1);
                     }
                 }
             }
@@ -93,6 +558,104 @@ fn main() {
                 list_themes(cfg);
             }
         },
-        Err(_) => println!("Could not deserialize config file"),
+        Err(e) => {
+            eprintln!(
+                "Could not read themes from {}: {}",
+                config_path.unwrap_or("the built-in themes"),
+                e
+            );
+            ::std::process::exit(1);
+        }
+    }
+}
+
+fn print_warnings(theme: &Theme) {
+    for warning in &theme.colors.warnings {
+        eprintln!("Warning: theme {}: {}", theme.name, warning);
+    }
+}
+
+/// The theme file for commands that write to it. Reading themes works without
+/// one.
+fn require_config(config_path: Option<&str>) -> &str {
+    config_path.unwrap_or_else(|| {
+        eprintln!("A theme file is required, pass it with '--config <FILE>'");
+        ::std::process::exit(1);
+    })
+}
+
+/// Contrast thresholds from the arguments of `check` and `fix-contrast`
+fn thresholds(args: &ArgMatches) -> Thresholds {
+    let parse = |name: &str| {
+        args.value_of(name).map(|v| {
+            v.parse::<f64>().unwrap_or_else(|_| {
+                eprintln!("--{} must be a number", name);
+                ::std::process::exit(1);
+            })
+        })
+    };
+
+    Thresholds {
+        min_ratio: parse("min-ratio").unwrap_or(4.5),
+        min_lc: parse("min-lc"),
+        ignore: args
+            .value_of("ignore")
+            .map(|i| i.split(',').map(|c| c.trim().to_owned()).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Reads every scheme file of the `import` command and appends the themes to
+/// the theme file. Nothing is written if any of them fails.
+fn import_themes(args: &ArgMatches, config_path: &Path) {
+    let files: Vec<&str> = args.values_of("FILES").unwrap_or_default().collect();
+
+    let result = (|| -> Result<Vec<ImportedTheme>, failure::Error> {
+        let mut file = ThemeFile::load(config_path)?;
+        let mut themes = vec![];
+
+        for path in &files {
+            let imported =
+                import::read(args.value_of("FORMAT").unwrap_or_default(), Path::new(path))
+                    .map_err(|e| failure::err_msg(format!("{}: {}", path, e)))?;
+
+            themes.extend(imported);
+        }
+
+        if let Some(name) = args.value_of("name") {
+            match &mut themes[..] {
+                [theme] => theme.name = name.to_owned(),
+                _ => return Err(failure::err_msg("--name only works for a single theme")),
+            }
+        }
+
+        for theme in &themes {
+            file.append(theme.to_value()?)?;
+        }
+
+        if args.is_present("dry-run") {
+            let values = themes
+                .iter()
+                .map(ImportedTheme::to_value)
+                .collect::<Result<Vec<_>, _>>()?;
+            println!("{}", serde_json::to_string_pretty(&values)?);
+        } else {
+            file.save(config_path)?;
+        }
+
+        Ok(themes)
+    })();
+
+    match result {
+        Ok(themes) if !args.is_present("dry-run") => {
+            for theme in themes {
+                println!("Added {} to {}", theme.name, config_path.display());
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            ::std::process::exit(1);
+        }
     }
 }
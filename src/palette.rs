@@ -0,0 +1,359 @@
+use crate::apps::color_index;
+use crate::literal::RawColor;
+use crate::reference;
+use crate::{ColorName, RGBA};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter::FromIterator;
+
+/// The named slots besides the 16 ANSI colors, in the order they are listed
+const SLOTS: [&str; 7] = [
+    "foreground",
+    "background",
+    "cursor",
+    "cursor_text",
+    "selection_foreground",
+    "selection_background",
+    "url",
+];
+
+/// Other names apps use for a slot, accepted in theme files
+const ALIASES: [(&str, &str); 5] = [
+    ("text", "cursor_text"),
+    ("cursor_foreground", "cursor_text"),
+    ("cursor_text_color", "cursor_text"),
+    ("highlight", "selection_background"),
+    ("url_color", "url"),
+];
+
+/// Extra colors that some app has a config key for
+const KNOWN_EXTRAS: [&str; 7] = [
+    "active_border_color",
+    "inactive_border_color",
+    "active_tab_foreground",
+    "active_tab_background",
+    "inactive_tab_foreground",
+    "inactive_tab_background",
+    "foreground_bold",
+];
+
+/// The colors of a theme: the 16 ANSI colors, the special colors every
+/// terminal has, and any other named colors (e.g., `color16` to `color255`
+/// or kitty's tab colors) in `extras`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Palette {
+    pub ansi: [Option<RGBA>; 16],
+    pub foreground: Option<RGBA>,
+    pub background: Option<RGBA>,
+    pub cursor: Option<RGBA>,
+    pub cursor_text: Option<RGBA>,
+    pub selection_foreground: Option<RGBA>,
+    pub selection_background: Option<RGBA>,
+    pub url: Option<RGBA>,
+    pub extras: BTreeMap<ColorName, RGBA>,
+    /// Problems found while reading the theme, e.g., misspelled names
+    pub warnings: Vec<String>,
+    /// Colors that were not in the theme but derived from it, see
+    /// `derivation::derive`
+    pub derived: BTreeSet<ColorName>,
+}
+
+impl Palette {
+    /// The palette name for `name`, resolving aliases like `text`
+    pub fn canonical_name(name: &str) -> &str {
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, slot)| slot)
+    }
+
+    /// Whether teems or any app knows what to do with a color called `name`
+    pub fn is_known_name(name: &str) -> bool {
+        let name = Palette::canonical_name(name);
+
+        color_index(name).is_some_and(|i| i <= 255)
+            || name
+                .strip_prefix("dim_")
+                .and_then(color_index)
+                .is_some_and(|i| i < 8)
+            || SLOTS.contains(&name)
+            || KNOWN_EXTRAS.contains(&name)
+    }
+
+    fn slot(&self, name: &str) -> Option<&Option<RGBA>> {
+        match Palette::canonical_name(name) {
+            "foreground" => Some(&self.foreground),
+            "background" => Some(&self.background),
+            "cursor" => Some(&self.cursor),
+            "cursor_text" => Some(&self.cursor_text),
+            "selection_foreground" => Some(&self.selection_foreground),
+            "selection_background" => Some(&self.selection_background),
+            "url" => Some(&self.url),
+            name => match color_index(name) {
+                Some(i) if i < 16 => Some(&self.ansi[i as usize]),
+                _ => None,
+            },
+        }
+    }
+
+    fn slot_mut(&mut self, name: &str) -> Option<&mut Option<RGBA>> {
+        match Palette::canonical_name(name) {
+            "foreground" => Some(&mut self.foreground),
+            "background" => Some(&mut self.background),
+            "cursor" => Some(&mut self.cursor),
+            "cursor_text" => Some(&mut self.cursor_text),
+            "selection_foreground" => Some(&mut self.selection_foreground),
+            "selection_background" => Some(&mut self.selection_background),
+            "url" => Some(&mut self.url),
+            name => match color_index(name) {
+                Some(i) if i < 16 => Some(&mut self.ansi[i as usize]),
+                _ => None,
+            },
+        }
+    }
+
+    /// The color called `name`, which can also be an alias
+    pub fn get(&self, name: &str) -> Option<&RGBA> {
+        match self.slot(name) {
+            Some(slot) => slot.as_ref(),
+            None => self.extras.get(name),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the color called `name` and returns the previous one
+    pub fn insert(&mut self, name: &str, color: RGBA) -> Option<RGBA> {
+        match self.slot_mut(name) {
+            Some(slot) => slot.replace(color),
+            None => self.extras.insert(name.to_owned(), color),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RGBA> {
+        match self.slot_mut(name) {
+            Some(slot) => slot.take(),
+            None => self.extras.remove(name),
+        }
+    }
+
+    /// All set colors with their palette names: ANSI colors first, then the
+    /// special colors, then the extras
+    pub fn iter(&self) -> impl Iterator<Item = (ColorName, &RGBA)> {
+        let ansi = self
+            .ansi
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.as_ref().map(|c| (format!("color{}", i), c)));
+
+        let slots = SLOTS
+            .iter()
+            .filter_map(move |name| self.get(name).map(|c| (name.to_string(), c)));
+
+        let extras = self.extras.iter().map(|(name, c)| (name.clone(), c));
+
+        ansi.chain(slots).chain(extras)
+    }
+
+    pub fn names(&self) -> Vec<ColorName> {
+        self.iter().map(|(name, _)| name).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keeps only the colors for which `keep` returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, &RGBA) -> bool,
+    {
+        let removed: Vec<ColorName> = self
+            .iter()
+            .filter(|(name, color)| !keep(name, color))
+            .map(|(name, _)| name)
+            .collect();
+
+        for name in removed {
+            self.remove(&name);
+        }
+    }
+
+    /// Builds a palette from a theme file's name to color map and records
+    /// warnings for names that are likely typos or set the same color twice
+    pub fn from_map(colors: HashMap<ColorName, RGBA>) -> Palette {
+        let mut names: Vec<&ColorName> = colors.keys().collect();
+        names.sort();
+
+        let mut palette = Palette::default();
+
+        for name in names {
+            if !Palette::is_known_name(name) {
+                palette.warnings.push(match suggestion(name) {
+                    Some(known) => format!("Unknown color '{}', did you mean '{}'?", name, known),
+                    None => format!("Unknown color '{}'", name),
+                });
+            }
+
+            if palette.insert(name, colors[name]).is_some() {
+                palette.warnings.push(format!(
+                    "'{}' is set more than once, under different names",
+                    Palette::canonical_name(name)
+                ));
+            }
+        }
+
+        palette
+    }
+
+    /// Parses the colors of a theme file, see `from_map`
+    pub fn from_raw(colors: HashMap<ColorName, RawColor>) -> Result<Palette, String> {
+        let mut raw: Vec<(ColorName, RawColor)> = colors.into_iter().collect();
+        raw.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(Palette::from_map(reference::resolve(&raw)?))
+    }
+}
+
+/// The known color name closest to `name`, if it is close enough to be a
+/// typo
+fn suggestion(name: &str) -> Option<String> {
+    let ansi = (0..16).map(|i| format!("color{}", i));
+    let named = SLOTS
+        .iter()
+        .chain(ALIASES.iter().map(|(alias, _)| alias))
+        .chain(KNOWN_EXTRAS.iter())
+        .map(|n| n.to_string());
+
+    ansi.chain(named)
+        .map(|known| (edit_distance(name, &known), known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + if ca == *cb { 0 } else { 1 };
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+impl FromIterator<(ColorName, RGBA)> for Palette {
+    fn from_iter<I: IntoIterator<Item = (ColorName, RGBA)>>(iter: I) -> Palette {
+        let mut palette = Palette::default();
+
+        for (name, color) in iter {
+            palette.insert(&name, color);
+        }
+
+        palette
+    }
+}
+
+impl<'d> Deserialize<'d> for Palette {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'d>,
+    {
+        Palette::from_raw(HashMap::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Palette {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+
+        for (name, color) in self.iter() {
+            map.serialize_entry(&name, color)?;
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_slots_aliases_and_extras() {
+        let palette: Palette = serde_json::from_str(
+            r##"{
+                "color3": "#030303",
+                "foreground": "#ffffff",
+                "text": "#010101",
+                "url_color": "#020202",
+                "color100": "#646464",
+                "active_tab_foreground": "#050505"
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(palette.ansi[3], Some(RGBA(3, 3, 3, 1.0)));
+        assert_eq!(palette.cursor_text, Some(RGBA(1, 1, 1, 1.0)));
+        assert_eq!(palette.get("cursor_foreground"), Some(&RGBA(1, 1, 1, 1.0)));
+        assert_eq!(palette.url, Some(RGBA(2, 2, 2, 1.0)));
+        assert_eq!(palette.get("color100"), Some(&RGBA(100, 100, 100, 1.0)));
+        assert!(palette.warnings.is_empty());
+
+        assert_eq!(
+            palette.names(),
+            vec![
+                "color3",
+                "foreground",
+                "cursor_text",
+                "url",
+                "active_tab_foreground",
+                "color100"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_warns_about_unknown_names() {
+        let palette: Palette = serde_json::from_str(
+            r##"{
+                "colour3": "#030303",
+                "foregound": "#ffffff",
+                "wallpaper": "#000000",
+                "text": "#010101",
+                "cursor_text": "#020202"
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            palette.warnings,
+            vec![
+                "Unknown color 'colour3', did you mean 'color3'?",
+                "Unknown color 'foregound', did you mean 'foreground'?",
+                "'cursor_text' is set more than once, under different names",
+                "Unknown color 'wallpaper'"
+            ]
+        );
+        // Unknown colors are kept, so they can still be used
+        assert_eq!(palette.get("wallpaper"), Some(&RGBA(0, 0, 0, 1.0)));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::literal::{self, RawColor};
+use crate::palette::Palette;
+use crate::{ColorName, RGBA};
+use std::collections::HashMap;
+
+/// Functions a theme color can be computed with, like
+/// `mix($color0, $color8, 0.5)`
+const FUNCTIONS: [&str; 5] = ["mix", "lighten", "darken", "saturate", "desaturate"];
+
+/// A theme color that is written in terms of other colors of the theme
+#[derive(Debug, PartialEq)]
+enum Expr {
+    /// `$color4`
+    Reference(ColorName),
+    Literal(RGBA),
+    Number(f64),
+    Call(String, Vec<Expr>),
+}
+
+/// Whether `value` has to be resolved against the rest of the theme instead
+/// of being parsed on its own
+fn is_expression(value: &str) -> bool {
+    let value = value.trim();
+
+    value.starts_with('$') || function_call(value).is_some()
+}
+
+/// The name and arguments of `value` if it calls one of `FUNCTIONS`
+fn function_call(value: &str) -> Option<(&str, &str)> {
+    let open = value.find('(')?;
+    let name = value[..open].trim();
+
+    if FUNCTIONS.contains(&name) {
+        Some((name, &value[open + 1..]))
+    } else {
+        None
+    }
+}
+
+/// Splits `args` at the commas that are not inside parentheses
+fn split_arguments(args: &str) -> Vec<&str> {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut parts = Vec::new();
+
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+
+    parts
+}
+
+fn parse(value: &str) -> Result<Expr, String> {
+    let value = value.trim();
+
+    if let Some(name) = value.strip_prefix('$') {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("'{}' is not a color reference", value));
+        }
+
+        return Ok(Expr::Reference(name.to_owned()));
+    }
+
+    if let Some((name, rest)) = function_call(value) {
+        let args = rest
+            .strip_suffix(')')
+            .ok_or_else(|| format!("missing ')' in '{}'", value))?;
+
+        let args = split_arguments(args)
+            .into_iter()
+            .map(parse)
+            .collect::<Result<Vec<Expr>, String>>()?;
+
+        return Ok(Expr::Call(name.to_owned(), args));
+    }
+
+    if let Some(percent) = value.strip_suffix('%') {
+        if let Ok(n) = percent.parse::<f64>() {
+            return Ok(Expr::Number(n / 100.0));
+        }
+    }
+
+    match value.parse::<f64>() {
+        Ok(n) => Ok(Expr::Number(n)),
+        Err(_) => literal::parse_color(value).map(Expr::Literal),
+    }
+}
+
+/// Resolves the colors of one theme, following references between them.
+/// Errors name the key they are about.
+pub fn resolve(colors: &[(ColorName, RawColor)]) -> Result<HashMap<ColorName, RGBA>, String> {
+    let mut resolver = Resolver {
+        colors: HashMap::new(),
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+    };
+
+    for (name, raw) in colors {
+        resolver
+            .colors
+            .entry(Palette::canonical_name(name).to_owned())
+            .or_insert((name, raw));
+    }
+
+    colors
+        .iter()
+        .map(|(name, raw)| {
+            let color = if resolver.colors[Palette::canonical_name(name)].0 == name {
+                resolver.color(name)?
+            } else {
+                // A color that is set more than once under different names.
+                // References use the first, but this key keeps its own value.
+                raw.parse_in(&mut resolver)
+                    .map_err(|e| format!("color '{}': {}", name, e))?
+            };
+
+            Ok((name.clone(), color))
+        })
+        .collect()
+}
+
+struct Resolver<'a> {
+    /// By canonical name
+    colors: HashMap<ColorName, (&'a ColorName, &'a RawColor)>,
+    resolved: HashMap<ColorName, RGBA>,
+    /// The colors being resolved, to detect cycles
+    stack: Vec<ColorName>,
+}
+
+impl<'a> Resolver<'a> {
+    fn color(&mut self, name: &str) -> Result<RGBA, String> {
+        let canonical = Palette::canonical_name(name).to_owned();
+
+        if let Some(color) = self.resolved.get(&canonical) {
+            return Ok(*color);
+        }
+
+        let (key, raw) = match self.colors.get(&canonical) {
+            Some(entry) => *entry,
+            None => return Err(format!("the theme has no color '{}'", name)),
+        };
+
+        if let Some(start) = self.stack.iter().position(|n| *n == canonical) {
+            let cycle: Vec<&str> = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|n| self.colors[n].0.as_str())
+                .collect();
+
+            return Err(format!(
+                "color '{}': references form a cycle: {}",
+                key,
+                cycle.join(" -> ")
+            ));
+        }
+
+        self.stack.push(canonical.clone());
+        let result = raw.parse_in(self);
+        self.stack.pop();
+
+        let color = result.map_err(|e| {
+            // Errors from referenced colors already name their key
+            if e.starts_with("color '") {
+                e
+            } else {
+                format!("color '{}': {}", key, e)
+            }
+        })?;
+
+        self.resolved.insert(canonical, color);
+
+        Ok(color)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<RGBA, String> {
+        match expr {
+            Expr::Reference(name) => self.color(name),
+            Expr::Literal(color) => Ok(*color),
+            Expr::Number(n) => Err(format!("expected a color, got {}", n)),
+            Expr::Call(function, args) => {
+                let (arity, expected) = match function.as_str() {
+                    "mix" => (2..=3, "2 or 3"),
+                    _ => (2..=2, "2"),
+                };
+
+                if !arity.contains(&args.len()) {
+                    return Err(format!("{}() takes {} arguments", function, expected));
+                }
+
+                let number = |i: usize| match args.get(i) {
+                    Some(Expr::Number(n)) => Ok(*n),
+                    Some(_) => Err(format!(
+                        "argument {} of {}() must be a number",
+                        i + 1,
+                        function
+                    )),
+                    // Only the weight of mix() is optional
+                    None => Ok(0.5),
+                };
+
+                let color = self.eval(&args[0])?;
+
+                match function.as_str() {
+                    "mix" => {
+                        let other = self.eval(&args[1])?;
+                        Ok(color.mix(&other, number(2)?))
+                    }
+                    "lighten" => Ok(color.lighten(number(1)?)),
+                    "darken" => Ok(color.darken(number(1)?)),
+                    "saturate" => Ok(color.saturate(number(1)?)),
+                    _ => Ok(color.saturate(-number(1)?)),
+                }
+            }
+        }
+    }
+}
+
+impl RawColor {
+    fn parse_in(&self, resolver: &mut Resolver) -> Result<RGBA, String> {
+        match self {
+            RawColor::Str(s) if is_expression(s) => resolver.eval(&parse(s)?),
+            raw => raw.parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(pairs: &[(&str, &str)]) -> Vec<(ColorName, RawColor)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), RawColor::Str(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn it_resolves_references_and_functions() {
+        let resolved = resolve(&colors(&[
+            ("color0", "#000000"),
+            ("color8", "#ffffff"),
+            ("cursor", "$color4"),
+            ("color4", "$text"),
+            ("text", "tomato"),
+            ("selection_background", "mix($color0, $color8, 0.5)"),
+            ("color12", "lighten(darken($color4, 10%), 0.1)"),
+        ]))
+        .unwrap();
+
+        assert_eq!(resolved["cursor"], RGBA(255, 99, 71, 1.0));
+        assert_eq!(resolved["color4"], RGBA(255, 99, 71, 1.0));
+        assert_eq!(
+            resolved["selection_background"],
+            RGBA(0, 0, 0, 1.0).mix(&RGBA(255, 255, 255, 1.0), 0.5)
+        );
+        assert_eq!(
+            resolved["color12"],
+            RGBA(255, 99, 71, 1.0).darken(0.1).lighten(0.1)
+        );
+    }
+
+    #[test]
+    fn it_names_the_key_in_errors() {
+        let error = |pairs| resolve(&colors(pairs)).unwrap_err();
+
+        assert_eq!(
+            error(&[("cursor", "$color4")]),
+            "color 'cursor': the theme has no color 'color4'"
+        );
+        assert_eq!(
+            error(&[
+                ("color1", "$color2"),
+                ("color2", "$color3"),
+                ("color3", "$color1")
+            ]),
+            "color 'color1': references form a cycle: color1 -> color2 -> color3 -> color1"
+        );
+        assert_eq!(
+            error(&[("cursor", "$color1"), ("color1", "#ggg")]),
+            "color 'color1': Invalid color '#ggg': not a hex number"
+        );
+        assert_eq!(
+            error(&[("color1", "mix($color0)"), ("color0", "red")]),
+            "color 'color1': mix() takes 2 or 3 arguments"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::apps::color_index;
+use crate::{Theme, RGBA};
+#[cfg(not(unix))]
+use failure::err_msg;
+use failure::Error;
+#[cfg(unix)]
+use std::fs::{self, OpenOptions};
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// OSC codes for the special colors. 17 and 19 are the highlight colors,
+/// which is what the configs call selection.
+const SPECIAL_COLORS: [(&str, u8); 5] = [
+    ("foreground", 10),
+    ("background", 11),
+    ("cursor", 12),
+    ("selection_background", 17),
+    ("selection_foreground", 19),
+];
+
+fn osc_color(color: &RGBA) -> String {
+    format!("rgb:{:02x}/{:02x}/{:02x}", color.0, color.1, color.2)
+}
+
+/// The OSC escape sequences that set the colors of `theme` in a running
+/// terminal. Terminated with BEL, which more terminals understand than ST.
+/// Derived colors past the 16 ANSI colors are left out, terminals have a
+/// 256-color palette of their own.
+pub fn sequences(theme: &Theme) -> String {
+    let mut palette: Vec<(u32, &RGBA)> = theme
+        .colors
+        .iter()
+        .filter_map(|(name, color)| match color_index(&name) {
+            Some(i) if i < 16 => Some((i, color)),
+            Some(i) if i <= 255 && !theme.colors.derived.contains(&name) => Some((i, color)),
+            _ => None,
+        })
+        .collect();
+    palette.sort_by_key(|(i, _)| *i);
+
+    let mut output = String::new();
+
+    for (i, color) in palette {
+        output.push_str(&format!("\u{1b}]4;{};{}\u{7}", i, osc_color(color)));
+    }
+
+    for (name, code) in &SPECIAL_COLORS {
+        if let Some(color) = theme.colors.get(name) {
+            output.push_str(&format!("\u{1b}]{};{}\u{7}", code, osc_color(color)));
+        }
+    }
+
+    output
+}
+
+/// The pseudo-terminals in `dir` (usually `/dev/pts`) that belong to the
+/// current user
+#[cfg(unix)]
+pub fn user_terminals(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let uid = unsafe { libc::getuid() };
+
+    let mut terminals: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        // Skips ptmx, which is the multiplexer and not a terminal
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter(|entry| {
+            entry
+                .metadata()
+                .map(|m| m.file_type().is_char_device() && m.uid() == uid)
+                .unwrap_or(false)
+        })
+        .map(|entry| entry.path())
+        .collect();
+
+    terminals.sort();
+
+    Ok(terminals)
+}
+
+/// Writes `sequences` to every terminal in `terminals` and returns the ones
+/// that could be written to. Terminals are opened without becoming the
+/// controlling terminal and without blocking on a full output buffer.
+#[cfg(unix)]
+pub fn send(terminals: &[PathBuf], sequences: &str) -> Vec<PathBuf> {
+    terminals
+        .iter()
+        .filter(|path| {
+            OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+                .open(path)
+                .and_then(|mut tty| tty.write_all(sequences.as_bytes()))
+                .is_ok()
+        })
+        .cloned()
+        .collect()
+}
+
+/// Recolors every terminal of the current user to `theme`
+#[cfg(unix)]
+pub fn apply_live(theme: &Theme) -> Result<Vec<PathBuf>, Error> {
+    let terminals = user_terminals(Path::new("/dev/pts"))?;
+
+    Ok(send(&terminals, &sequences(theme)))
+}
+
+// Terminals are found and written to as unix pseudo-terminals
+#[cfg(not(unix))]
+pub fn apply_live(_theme: &Theme) -> Result<Vec<PathBuf>, Error> {
+    Err(err_msg(
+        "Recoloring running terminals is only supported on unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_theme() -> Theme {
+        let colors = vec![
+            (String::from("color10"), RGBA(10, 10, 10, 1.0)),
+            (String::from("color1"), RGBA(1, 1, 1, 1.0)),
+            (String::from("foreground"), RGBA(255, 255, 255, 1.0)),
+            (String::from("selection_foreground"), RGBA(0, 128, 255, 1.0)),
+            (String::from("color300"), RGBA(3, 3, 3, 1.0)),
+            (String::from("url"), RGBA(3, 3, 3, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+
+        Theme {
+            name: String::from("foo"),
+            extends: None,
+            colors,
+        }
+    }
+
+    #[test]
+    fn it_builds_sequences() {
+        assert_eq!(
+            sequences(&get_theme()),
+            "\u{1b}]4;1;rgb:01/01/01\u{7}\
+             \u{1b}]4;10;rgb:0a/0a/0a\u{7}\
+             \u{1b}]10;rgb:ff/ff/ff\u{7}\
+             \u{1b}]19;rgb:00/80/ff\u{7}"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_writes_sequences_to_a_pty() {
+        use std::ffi::CStr;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let (mut master, slave) = unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+
+            let mut name = [0 as libc::c_char; 64];
+            assert_eq!(libc::ptsname_r(fd, name.as_mut_ptr(), name.len()), 0);
+            let name = CStr::from_ptr(name.as_ptr()).to_string_lossy();
+
+            (File::from_raw_fd(fd), PathBuf::from(name.into_owned()))
+        };
+
+        // Keep the slave side open, so the output stays readable
+        let _open = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&slave)
+            .unwrap();
+
+        let seq = sequences(&get_theme());
+        assert_eq!(send(std::slice::from_ref(&slave), &seq), vec![slave]);
+
+        let mut buffer = vec![0; seq.len()];
+        master.read_exact(&mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), seq);
+    }
+}
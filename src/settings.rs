@@ -0,0 +1,198 @@
+use crate::apps;
+use failure::{err_msg, Error};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How teems changes an app's config file
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Replace the color values in existing lines
+    #[default]
+    Rewrite,
+    /// Regenerate everything between `# BEGIN teems <app>` and
+    /// `# END teems <app>`
+    Block,
+    /// Write the colors to a separate file that the config includes
+    Include,
+}
+
+/// Per-app options from the teems settings file.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AppSettings {
+    /// Set to `false` to never touch this app's configs
+    pub enabled: Option<bool>,
+    /// Replaces the app's default config locations
+    pub paths: Option<Vec<PathBuf>>,
+    /// Config locations used in addition to `paths` or the defaults
+    #[serde(default)]
+    pub extra_paths: Vec<PathBuf>,
+    /// Add entries for theme colors the config doesn't have a key for yet
+    pub insert_missing: Option<bool>,
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Set to `false` to skip the built-in reload after an activation
+    pub reload: Option<bool>,
+}
+
+/// The teems settings file, usually `~/.config/teems/config.toml`:
+///
+/// ```toml
+/// derive = true
+/// hooks = ["notify-send \"Switched to $TEEMS_THEME\""]
+///
+/// [apps.kitty]
+/// paths = ["~/dotfiles/kitty/kitty.conf"]
+/// insert_missing = true
+///
+/// [apps.alacritty]
+/// strategy = "block"
+///
+/// [apps.xterm]
+/// enabled = false
+/// ```
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub apps: HashMap<String, AppSettings>,
+    /// Shell commands that run after every activation, see `hooks::run`
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// Fill colors the themes don't set, see `derivation::derive`
+    #[serde(default)]
+    pub derive: bool,
+}
+
+impl Settings {
+    pub fn default_path() -> PathBuf {
+        apps::config_home().join("teems").join("config.toml")
+    }
+
+    /// Reads the settings from `path`. A missing file means default settings.
+    pub fn load(path: &Path) -> Result<Settings, Error> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+
+        Settings::parse(&contents)
+            .map_err(|e| err_msg(format!("Invalid settings in {}: {}", path.display(), e)))
+    }
+
+    pub fn parse(contents: &str) -> Result<Settings, Error> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    pub fn app(&self, name: &str) -> Option<&AppSettings> {
+        self.apps.get(name)
+    }
+
+    pub fn strategy(&self, name: &str) -> Strategy {
+        self.app(name).map(|a| a.strategy).unwrap_or_default()
+    }
+
+    pub fn reloads(&self, name: &str) -> bool {
+        self.app(name).and_then(|a| a.reload).unwrap_or(true)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.app(name).and_then(|a| a.enabled).unwrap_or(true)
+    }
+}
+
+/// Expands a leading `~` to the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => apps::home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_settings() {
+        let s = r#"
+            hooks = ["pkill -USR1 polybar"]
+
+            [apps.kitty]
+            paths = ["~/dotfiles/kitty.conf"]
+            extra_paths = ["/etc/kitty.conf"]
+
+            [apps.xterm]
+            enabled = false
+            strategy = "block"
+            reload = false
+        "#;
+
+        let settings = Settings::parse(s).unwrap();
+
+        let kitty = settings.app("kitty").unwrap();
+        assert_eq!(
+            kitty.paths,
+            Some(vec![PathBuf::from("~/dotfiles/kitty.conf")])
+        );
+        assert_eq!(kitty.extra_paths, vec![PathBuf::from("/etc/kitty.conf")]);
+
+        assert!(settings.is_enabled("kitty"));
+        assert!(!settings.is_enabled("xterm"));
+        assert!(settings.is_enabled("alacritty"));
+
+        assert_eq!(settings.strategy("kitty"), Strategy::Rewrite);
+        assert_eq!(settings.strategy("xterm"), Strategy::Block);
+
+        assert!(settings.reloads("kitty"));
+        assert!(!settings.reloads("xterm"));
+        assert_eq!(settings.hooks, vec![String::from("pkill -USR1 polybar")]);
+    }
+
+    #[test]
+    fn it_parses_the_documented_example() {
+        let s = r#"
+            derive = true
+            hooks = ["notify-send \"Switched to $TEEMS_THEME\""]
+
+            [apps.kitty]
+            paths = ["~/dotfiles/kitty/kitty.conf"]
+            insert_missing = true
+
+            [apps.alacritty]
+            strategy = "block"
+
+            [apps.xterm]
+            enabled = false
+        "#;
+
+        let settings = Settings::parse(s).unwrap();
+
+        assert!(settings.derive);
+        assert_eq!(
+            settings.hooks,
+            vec![String::from("notify-send \"Switched to $TEEMS_THEME\"")]
+        );
+        assert_eq!(settings.app("kitty").unwrap().insert_missing, Some(true));
+        assert_eq!(settings.strategy("alacritty"), Strategy::Block);
+        assert!(!settings.is_enabled("xterm"));
+    }
+
+    #[test]
+    fn it_rejects_unknown_fields() {
+        assert!(Settings::parse("[apps.kitty]\npath = []").is_err());
+    }
+
+    #[test]
+    fn it_expands_home() {
+        assert_eq!(
+            expand_home(Path::new("~/foo")),
+            apps::home_dir().join("foo")
+        );
+        assert_eq!(expand_home(Path::new("/foo")), PathBuf::from("/foo"));
+    }
+}
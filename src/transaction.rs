@@ -0,0 +1,160 @@
+use crate::FileChange;
+use failure::{err_msg, Error};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Replaces the contents of `path` without ever leaving a partially written
+/// file behind: the new contents go to a temporary file next to the target,
+/// which is synced and then renamed over it. Symlinks are followed, so the
+/// link itself stays in place (e.g., for stow managed dotfiles), and the
+/// permissions of the existing file are kept. A file that doesn't exist yet
+/// is created.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), Error> {
+    let (target, permissions) = if path.exists() {
+        let target = fs::canonicalize(path)?;
+        let permissions = fs::metadata(&target)?.permissions();
+        (target, Some(permissions))
+    } else {
+        (path.to_path_buf(), None)
+    };
+
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| err_msg(format!("Not a file: {}", target.display())))?
+        .to_string_lossy();
+    let tmp = target.with_file_name(format!(".{}.teems-tmp", file_name));
+
+    let result = (|| -> Result<(), Error> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&tmp, &target)?;
+
+        // Persist the rename itself
+        if let Some(dir) = target.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    })();
+
+    if result.is_err() && tmp.exists() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Writes all modified files or none of them. If a write fails, the files
+/// that were already written get their old contents back, and files that
+/// were created are removed again.
+pub fn commit(changes: &[FileChange]) -> Result<(), Error> {
+    let mut written: Vec<&FileChange> = vec![];
+
+    for change in changes.iter().filter(|c| c.is_modified()) {
+        if let Err(e) = write_atomic(&change.path, &change.new) {
+            let failed: Vec<PathBuf> = written
+                .iter()
+                .rev()
+                .filter(|c| {
+                    if c.created {
+                        fs::remove_file(&c.path).is_err()
+                    } else {
+                        write_atomic(&c.path, &c.old).is_err()
+                    }
+                })
+                .map(|c| c.path.clone())
+                .collect();
+
+            return Err(if failed.is_empty() {
+                err_msg(format!(
+                    "Could not write {}, no files were changed: {}",
+                    change.path.display(),
+                    e
+                ))
+            } else {
+                err_msg(format!(
+                    "Could not write {}: {}. Rolling back failed for: {}",
+                    change.path.display(),
+                    e,
+                    failed
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ))
+            });
+        }
+
+        written.push(change);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(path: PathBuf, old: &str, new: &str) -> FileChange {
+        FileChange {
+            app: String::from("kitty"),
+            path,
+            old: old.to_owned(),
+            new: new.to_owned(),
+            ..FileChange::default()
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_follows_symlinks_and_keeps_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("kitty.conf");
+        let link = dir.path().join("link.conf");
+
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_atomic(&link, "new").unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn it_rolls_back_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.conf");
+        let created = dir.path().join("created.conf");
+        let missing = dir.path().join("missing/second.conf");
+
+        fs::write(&first, "old").unwrap();
+
+        let changes = vec![
+            change(first.clone(), "old", "new"),
+            FileChange {
+                created: true,
+                ..change(created.clone(), "", "new")
+            },
+            change(missing, "old", "new"),
+        ];
+
+        assert!(commit(&changes).is_err());
+        assert_eq!(fs::read_to_string(&first).unwrap(), "old");
+        assert!(!created.exists());
+    }
+}